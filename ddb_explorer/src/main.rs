@@ -3,13 +3,24 @@ use crossbeam::{
     select,
 };
 use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
-use ddb_lib::{Id, Message};
+use ddb_lib::{
+    Blob, Capability, Cursor, EntryHash, Id, KeyEvent, MAX_ENTRY, Message, MessageType, Network,
+    SecretId, SequenceNumber, SwapOutcome, Value,
+};
 use std::{
+    fs,
     io::{self, Read},
-    net::{IpAddr, Ipv4Addr, SocketAddr, ToSocketAddrs, UdpSocket},
+    net::{IpAddr, Ipv4Addr, SocketAddr, ToSocketAddrs},
+    sync::{Arc, Mutex},
     thread::{self},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
+/// Number of keys or entries asked for at a time by `keys`, `scan`, `author` and `changes`
+const PAGE_SIZE: usize = 20;
+/// Largest value the explorer will send, leaving room for the rest of the entry the node writes
+const MAX_VALUE: usize = MAX_ENTRY - 1024;
+
 mod ui;
 use ui::UiMessage;
 
@@ -25,10 +36,14 @@ fn main() -> Result<(), io::Error> {
     thread::spawn(move || ui_thread(ui_in_rx, ui_out_tx));
     thread::spawn(|| input_thread(in_tx));
 
-    let mut sock = Option::<UdpSocket>::None;
+    let mut conn = Option::<Connection>::None;
     let mut net_rx = None;
     let mut port = 1500u16;
-    let mut id = Id::default();
+    // key used to sign control messages, the node must list its id as an operator
+    let mut secret = SecretId::generate();
+    // the last `keys` or `scan` request, given the key to continue after, and where the next page starts
+    let mut page_request = Option::<Box<dyn Fn(Option<String>) -> Message>>::None;
+    let mut next_page = Option::<String>::None;
 
     loop {
         select! {
@@ -47,31 +62,72 @@ fn main() -> Result<(), io::Error> {
                 match command {
                     "q" | "quit" => {break;}
                     "id" => {
-                        let Some(new_id) = parts.next().map(|str| str.parse() ) else {let _ = ui_in_tx.send(UiMessage::Message(format!("Current id is {id}"))); continue;};
-                        if let Ok(new_id) = new_id {
-                            id = new_id;
+                        let _ = ui_in_tx.send(UiMessage::Message(format!("Current id is {}", secret.id())));
+                    }
+                    "key" => {
+                        // load the signing key from a key file, or generate a new one
+                        let Some(path) = parts.next() else {let _ = ui_in_tx.send(UiMessage::Message("Requires a key file path or `new`".into())); continue;};
+                        let new_secret = if path == "new" {
+                            Some(SecretId::generate())
+                        } else {
+                            fs::read_to_string(path).ok().and_then(|data| data.parse().ok())
+                        };
+                        if let Some(new_secret) = new_secret {
+                            secret = new_secret;
+                            if let Some(conn) = conn.as_ref() {
+                                conn.network.lock().unwrap().set_secret(secret.clone());
+                            }
+                            let _ = ui_in_tx.send(UiMessage::Message(format!("Id set to {}", secret.id())));
+                        }else{
+                            let _ = ui_in_tx.send(UiMessage::Message(format!("Could not read key from {path}")));
+                        }
+                    }
+                    "rotate" => {
+                        // hand our key's trust over to a successor, which signs future commands
+                        if let Some(conn) = conn.as_ref() {
+                            let Some(path) = parts.next() else {let _ = ui_in_tx.send(UiMessage::Message("Requires a key file path or `new`".into())); continue;};
+                            let successor = if path == "new" {
+                                Some(SecretId::generate())
+                            } else {
+                                fs::read_to_string(path).ok().and_then(|data| data.parse().ok())
+                            };
+                            let Some(successor) = successor else {let _ = ui_in_tx.send(UiMessage::Message(format!("Could not read key from {path}"))); continue;};
+                            conn.send(Message::key_event(secret.id(), KeyEvent::rotate(&secret, &successor)).signed(&secret));
+                            secret = successor;
+                            conn.network.lock().unwrap().set_secret(secret.clone());
+                            let _ = ui_in_tx.send(UiMessage::Message(format!("Rotated, id set to {}", secret.id())));
                         }else{
-                            id = Id::default();
+                            let _ = ui_in_tx.send(UiMessage::Message("Not Connected".into()));
+                        }
+                    }
+                    "revoke" => {
+                        // tell the network to stop trusting our key, and everything it wrote
+                        if let Some(conn) = conn.as_ref() {
+                            conn.send(Message::key_event(secret.id(), KeyEvent::revoke(&secret)).signed(&secret));
+                            secret = SecretId::generate();
+                            conn.network.lock().unwrap().set_secret(secret.clone());
+                            let _ = ui_in_tx.send(UiMessage::Message(format!("Revoked, id set to new {}", secret.id())));
+                        }else{
+                            let _ = ui_in_tx.send(UiMessage::Message("Not Connected".into()));
                         }
-                        let _ = ui_in_tx.send(UiMessage::Message(format!("Id set to {:?}", id)));
                     }
                     "port" => {
                         let Some(new_port) = parts.next().map(|str| str.parse() ) else {let _ = ui_in_tx.send(UiMessage::Message(format!("Current port is {port}"))); continue;};
                         if let Ok(new_port) = new_port {
                             port = new_port;
                         }else{
-                            let _ = ui_in_tx.send(UiMessage::Message(format!("port not changed")));
+                            let _ = ui_in_tx.send(UiMessage::Message("port not changed".to_string()));
                         }
                         let _ = ui_in_tx.send(UiMessage::Message(format!("Port set to {:?}", port)));
                     }
                     "connect" => {
                         match parts.next() {
                             Some(addr) => {
-                                sock = None;
+                                conn = None;
                                 net_rx = None;
-                                if let Some((new_sock, new_rx)) = create_network_thread((IpAddr::V4(Ipv4Addr::UNSPECIFIED), port), addr) {
-                                    let _ = ui_in_tx.send(UiMessage::Message(format!("Connected to: {:?}", new_sock.peer_addr())));
-                                    sock = Some(new_sock);
+                                if let Some((new_conn, new_rx)) = create_network_thread((IpAddr::V4(Ipv4Addr::UNSPECIFIED), port), addr, secret.clone()) {
+                                    let _ = ui_in_tx.send(UiMessage::Message(format!("Connected to: {:?}", new_conn.node)));
+                                    conn = Some(new_conn);
                                     net_rx = Some(new_rx);
                                 } else {
                                     let _ = ui_in_tx.send(UiMessage::Message("failed to connect".into()));
@@ -82,25 +138,178 @@ fn main() -> Result<(), io::Error> {
 
                     }
                     "disconnect" => {
-                        sock = None;
+                        conn = None;
                         net_rx = None;
                         let _ = ui_in_tx.send(UiMessage::Message("Disconnected".into()));
                     },
                     "get" => {
-                        if let Some(sock) = sock.as_ref() {
+                        if let Some(conn) = conn.as_ref() {
                             let Some(key) = parts.next() else {let _ = ui_in_tx.send(UiMessage::Message("Key required".into())); continue;};
-                            let count = parts.next().map_or(1, |part|{let x = part.parse::<usize>().unwrap_or(1); x});
-                            sock.send(&Message::get(id, key.to_string(), count).serialize());
+                            let count = parts.next().map_or(1, |part|{ part.parse::<usize>().unwrap_or(1)});
+                            conn.send(Message::get(secret.id(), key.to_string(), count).signed(&secret));
+                        }else{
+                            let _ = ui_in_tx.send(UiMessage::Message("Not Connected".into()));
+                        }
+                    }
+                    "keys" | "scan" => {
+                        // list keys by prefix, or the newest entries of a range of keys, a page at a time
+                        if let Some(conn) = conn.as_ref() {
+                            let id = secret.id();
+                            let first = parts.next().unwrap_or_default().to_string();
+                            let request: Box<dyn Fn(Option<String>) -> Message> = if command == "keys" {
+                                Box::new(move |after| Message::list_keys(id, first.clone(), after, PAGE_SIZE))
+                            } else {
+                                let end = parts.next().map(str::to_string);
+                                Box::new(move |after| Message::scan(id, first.clone(), end.clone(), after, PAGE_SIZE))
+                            };
+                            conn.send(request(None).signed(&secret));
+                            page_request = Some(request);
+                            next_page = None;
+                        }else{
+                            let _ = ui_in_tx.send(UiMessage::Message("Not Connected".into()));
+                        }
+                    }
+                    "author" | "changes" => {
+                        // everything an author wrote, or what changed on a key since a sequence number, oldest first
+                        if let Some(conn) = conn.as_ref() {
+                            let id = secret.id();
+                            let request: Box<dyn Fn(Option<String>) -> Message> = if command == "author" {
+                                let Some(Ok(author)) = parts.next().map(|str| str.parse::<Id>()) else {let _ = ui_in_tx.send(UiMessage::Message("Missing required id".to_string())); continue;};
+                                Box::new(move |after| Message::get_by_author(id, author, after.and_then(|after| after.parse().ok()), PAGE_SIZE))
+                            } else {
+                                let Some(key) = parts.next().map(str::to_string) else {let _ = ui_in_tx.send(UiMessage::Message("Key required".into())); continue;};
+                                let since = parts.next().and_then(|since| since.parse::<SequenceNumber>().ok()).map(Cursor::from);
+                                Box::new(move |after| Message::get_changes(id, key.clone(), after.and_then(|after| after.parse().ok()).or(since.clone()), PAGE_SIZE))
+                            };
+                            conn.send(request(None).signed(&secret));
+                            page_request = Some(request);
+                            next_page = None;
+                        }else{
+                            let _ = ui_in_tx.send(UiMessage::Message("Not Connected".into()));
+                        }
+                    }
+                    "more" => {
+                        // the next page of the last `keys`, `scan`, `author` or `changes`
+                        if let Some(conn) = conn.as_ref() {
+                            let (Some(request), Some(after)) = (page_request.as_ref(), next_page.take()) else {let _ = ui_in_tx.send(UiMessage::Message("Nothing more to show".into())); continue;};
+                            conn.send(request(Some(after)).signed(&secret));
+                        }else{
+                            let _ = ui_in_tx.send(UiMessage::Message("Not Connected".into()));
+                        }
+                    }
+                    "heads" => {
+                        // show the current, possibly conflicting, values of a key
+                        if let Some(conn) = conn.as_ref() {
+                            let Some(key) = parts.next() else {let _ = ui_in_tx.send(UiMessage::Message("Key required".into())); continue;};
+                            conn.send(Message::get_heads(secret.id(), key.to_string()).signed(&secret));
+                        }else{
+                            let _ = ui_in_tx.send(UiMessage::Message("Not Connected".into()));
+                        }
+                    }
+                    "resolve" => {
+                        // ask the node to pick one value, ranked by how much it trusts the authors
+                        if let Some(conn) = conn.as_ref() {
+                            let Some(key) = parts.next() else {let _ = ui_in_tx.send(UiMessage::Message("Key required".into())); continue;};
+                            conn.send(Message::resolve(secret.id(), key.to_string()).signed(&secret));
                         }else{
                             let _ = ui_in_tx.send(UiMessage::Message("Not Connected".into()));
                         }
                     }
                     "set" => {
                         // make and send the message for the node to set the data
-                        if let Some(sock) = sock.as_ref() {
+                        if let Some(conn) = conn.as_ref() {
+                            let Some(key) = parts.next() else {let _ = ui_in_tx.send(UiMessage::Message("Key required".into())); continue;};
+                            let value = rest_of_command(&cmd, 2);
+                            conn.send(Message::set(secret.id(), key.to_string(), value.into()).signed(&secret));
+                        }else{
+                            let _ = ui_in_tx.send(UiMessage::Message("Not Connected".into()));
+                        }
+                    }
+                    "batch" => {
+                        // set several keys together, given as key=value
+                        if let Some(conn) = conn.as_ref() {
+                            let Some(writes) = parts.map(|part| part.split_once('=').map(|(key, value)| (key.to_string(), value.into()))).collect::<Option<Vec<(String, Value)>>>() else {let _ = ui_in_tx.send(UiMessage::Message("Writes must be given as key=value".into())); continue;};
+                            if writes.is_empty() {let _ = ui_in_tx.send(UiMessage::Message("At least one key=value required".into())); continue;}
+                            conn.send(Message::batch(secret.id(), writes).signed(&secret));
+                        }else{
+                            let _ = ui_in_tx.send(UiMessage::Message("Not Connected".into()));
+                        }
+                    }
+                    "setttl" => {
+                        // set data that expires after a number of seconds
+                        if let Some(conn) = conn.as_ref() {
+                            let Some(key) = parts.next() else {let _ = ui_in_tx.send(UiMessage::Message("Key required".into())); continue;};
+                            let Some(Ok(secs)) = parts.next().map(|str| str.parse::<u64>() ) else {let _ = ui_in_tx.send(UiMessage::Message("Missing required time to live in seconds".to_string())); continue;};
+                            let value = rest_of_command(&cmd, 3);
+                            conn.send(Message::set_expiring(secret.id(), key.to_string(), value.into(), secs.saturating_mul(1000)).signed(&secret));
+                        }else{
+                            let _ = ui_in_tx.send(UiMessage::Message("Not Connected".into()));
+                        }
+                    }
+                    "cas" => {
+                        // set the data only if the key's head is still the one we read, `none` if it should have no value
+                        if let Some(conn) = conn.as_ref() {
+                            let Some(key) = parts.next() else {let _ = ui_in_tx.send(UiMessage::Message("Key required".into())); continue;};
+                            let expected = match parts.next() {
+                                Some("none") => None,
+                                Some(hash) => match hash.parse::<EntryHash>() {
+                                    Ok(hash) => Some(hash),
+                                    Err(_) => {let _ = ui_in_tx.send(UiMessage::Message("Invalid head hash".into())); continue;},
+                                },
+                                None => {let _ = ui_in_tx.send(UiMessage::Message("Expected head hash required, see `heads`".into())); continue;},
+                            };
+                            let value = rest_of_command(&cmd, 3);
+                            conn.send(Message::compare_and_set(secret.id(), key.to_string(), expected, value.into()).signed(&secret));
+                        }else{
+                            let _ = ui_in_tx.send(UiMessage::Message("Not Connected".into()));
+                        }
+                    }
+                    "setfile" => {
+                        // set a value to the exact contents of a file, optionally tagged with a content type
+                        if let Some(conn) = conn.as_ref() {
+                            let (Some(key), Some(path)) = (parts.next(), parts.next()) else {let _ = ui_in_tx.send(UiMessage::Message("Key and file path required".into())); continue;};
+                            let content_type = parts.next().map(str::to_string);
+                            let data = match fs::read(path) {
+                                Ok(data) => data,
+                                Err(err) => {let _ = ui_in_tx.send(UiMessage::Message(format!("Could not read {path}: {err}"))); continue;}
+                            };
+                            if data.len() > MAX_VALUE {
+                                let _ = ui_in_tx.send(UiMessage::Message(format!("{path} is larger than {MAX_VALUE} bytes"))); continue;
+                            }
+                            conn.send(Message::set(secret.id(), key.to_string(), Value::Lww(Blob::new(content_type, data))).signed(&secret));
+                        }else{
+                            let _ = ui_in_tx.send(UiMessage::Message("Not Connected".into()));
+                        }
+                    }
+                    "delete" => {
+                        if let Some(conn) = conn.as_ref() {
+                            let Some(key) = parts.next() else {let _ = ui_in_tx.send(UiMessage::Message("Key required".into())); continue;};
+                            conn.send(Message::delete(secret.id(), key.to_string()).signed(&secret));
+                        }else{
+                            let _ = ui_in_tx.send(UiMessage::Message("Not Connected".into()));
+                        }
+                    }
+                    "incr" => {
+                        // change a counter, concurrent changes from other writers are all counted
+                        if let Some(conn) = conn.as_ref() {
                             let Some(key) = parts.next() else {let _ = ui_in_tx.send(UiMessage::Message("Key required".into())); continue;};
-                            let value = parts.collect::<Vec<_>>().join(" ");
-                            sock.send(&Message::set(id, key.to_string(), value).serialize());
+                            let Ok(amount) = parts.next().map_or(Ok(1), |part| part.parse::<i64>()) else {let _ = ui_in_tx.send(UiMessage::Message("Amount must be a whole number".into())); continue;};
+                            conn.send(Message::set(secret.id(), key.to_string(), Value::PnCounter(amount)).signed(&secret));
+                        }else{
+                            let _ = ui_in_tx.send(UiMessage::Message("Not Connected".into()));
+                        }
+                    }
+                    "add" | "remove" => {
+                        // change a set, an add made concurrently with a remove survives it
+                        if let Some(conn) = conn.as_ref() {
+                            let Some(key) = parts.next() else {let _ = ui_in_tx.send(UiMessage::Message("Key required".into())); continue;};
+                            let elem = rest_of_command(&cmd, 2).to_string();
+                            let value = if command == "add" {
+                                Value::SetAdd(elem)
+                            } else {
+                                Value::SetRemove { elem, observed: Vec::new() }
+                            };
+                            conn.send(Message::set(secret.id(), key.to_string(), value).signed(&secret));
                         }else{
                             let _ = ui_in_tx.send(UiMessage::Message("Not Connected".into()));
                         }
@@ -108,23 +317,40 @@ fn main() -> Result<(), io::Error> {
                     "link" => {
                         // instruct node to connect to new other node
 
-                        if let Some(sock) = sock.as_ref() {
+                        if let Some(conn) = conn.as_ref() {
                             if let Some(addr) = parts.next() {
                                 let _ = ui_in_tx.send(UiMessage::Message(format!("Linking to {}", addr)));
-                                sock.send(&Message::link(id, addr.into()).serialize());
+                                conn.send(Message::link(secret.id(), addr.into()).signed(&secret));
                             }else{
-                                let _ = ui_in_tx.send(UiMessage::Message(format!("Requires address to which to link")));
+                                let _ = ui_in_tx.send(UiMessage::Message("Requires address to which to link".to_string()));
                             }
                         }else{
                             let _ = ui_in_tx.send(UiMessage::Message("Not Connected".into()));
                         }
                     }
+                    "grant" => {
+                        // let an id write under a prefix of our own namespace, sent to the node in case it is the grantee
+                        if let Some(conn) = conn.as_ref() {
+                            let Some(Ok(grantee)) = parts.next().map(|str| str.parse::<Id>() ) else {let _ = ui_in_tx.send(UiMessage::Message("Missing required id".to_string())); continue;};
+                            let Some(prefix) = parts.next() else {let _ = ui_in_tx.send(UiMessage::Message("Prefix required".into())); continue;};
+                            let Some(Ok(secs)) = parts.next().map(|str| str.parse::<u64>() ) else {let _ = ui_in_tx.send(UiMessage::Message("Missing required duration in seconds".to_string())); continue;};
+                            let delegable = parts.next() == Some("delegable");
+
+                            let prefix = format!("{}/{prefix}", secret.id());
+                            let expiry = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_millis() as u64) + secs * 1000;
+                            let capability = Capability::new(&secret, grantee, prefix, expiry, delegable);
+                            let _ = ui_in_tx.send(UiMessage::Message(format!("Granted {grantee} writes under {} for {secs}s", capability.prefix)));
+                            conn.send(Message::grant(secret.id(), vec![capability]).signed(&secret));
+                        }else{
+                            let _ = ui_in_tx.send(UiMessage::Message("Not Connected".into()));
+                        }
+                    }
                     "trust" => {
-                        if let Some(sock) = sock.as_ref() {
-                            let Some(Ok(target_id)) = parts.next().map(|str| str.parse::<u16>() ) else {let _ = ui_in_tx.send(UiMessage::Message(format!("Missing required id"))); continue;};
-                            let Some(Ok(trust_delta)) = parts.next().map(|str| str.parse::<i16>() ) else {let _ = ui_in_tx.send(UiMessage::Message(format!("Missing required change in trust"))); continue;};
+                        if let Some(conn) = conn.as_ref() {
+                            let Some(Ok(target_id)) = parts.next().map(|str| str.parse::<Id>() ) else {let _ = ui_in_tx.send(UiMessage::Message("Missing required id".to_string())); continue;};
+                            let Some(Ok(trust_delta)) = parts.next().map(|str| str.parse::<i16>() ) else {let _ = ui_in_tx.send(UiMessage::Message("Missing required change in trust".to_string())); continue;};
 
-                            sock.send(&Message::trust(id, target_id.into(), trust_delta).serialize());
+                            conn.send(Message::trust(secret.id(), target_id, trust_delta).signed(&secret));
                         }else{
                             let _ = ui_in_tx.send(UiMessage::Message("Not Connected".into()));
                         }
@@ -135,19 +361,97 @@ fn main() -> Result<(), io::Error> {
             recv(net_rx.as_ref().unwrap_or(&never())) -> res => {
                 let Ok((_addr, msg)) = res else {continue;};
                 match msg.take_msg_type() {
-                    ddb_lib::MessageType::Verify(challenge, _pad) => {sock.as_ref().unwrap().send(Message::verified(Id::generate(), challenge, false).serialize().as_slice());},
-                    ddb_lib::MessageType::Verified(_challenge, _is_neighbor) => {},// explorer never requests verification
+                    ddb_lib::MessageType::Verify { .. } => {}, // handled by the network thread
+                    ddb_lib::MessageType::Verified { .. } => {}, // handled by the network thread
                     ddb_lib::MessageType::Get { key: _, count: _ } => {}, // Explorer should not be asked this
+                    ddb_lib::MessageType::Got { key, state, entries } => {
+                        match state {
+                            Some(state) => {let _ = ui_in_tx.send(UiMessage::Message(format!("Value: {key}={state}")));},
+                            None => {let _ = ui_in_tx.send(UiMessage::Message(format!("{key} has no values")));},
+                        }
+                        for entry in entries {
+                            let _ = ui_in_tx.send(UiMessage::Message(format!("Got data: {}={}", entry.key, entry.val)));
+                        }
+                    },
                     ddb_lib::MessageType::Values(items) => {
                         for entry in items {
                             let _ = ui_in_tx.send(UiMessage::Message(format!("Got data: {}={}", entry.key, entry.val)));
                         }
                     },
-                    ddb_lib::MessageType::Set(_entry) => {}, // Explorer does not store items
+                    ddb_lib::MessageType::ListKeys { .. } => {}, // Explorer should not be asked this
+                    ddb_lib::MessageType::Keys { keys, next } => {
+                        for key in keys {
+                            let _ = ui_in_tx.send(UiMessage::Message(format!("Key: {key}")));
+                        }
+                        if next.is_some() {
+                            let _ = ui_in_tx.send(UiMessage::Message("More keys follow, use `more` to see them".into()));
+                        }
+                        next_page = next;
+                    },
+                    ddb_lib::MessageType::Scan { .. } => {}, // Explorer should not be asked this
+                    ddb_lib::MessageType::Scanned { entries, next } => {
+                        for entry in entries {
+                            let _ = ui_in_tx.send(UiMessage::Message(format!("Got data: {}={}", entry.key, entry.val)));
+                        }
+                        if next.is_some() {
+                            let _ = ui_in_tx.send(UiMessage::Message("More entries follow, use `more` to see them".into()));
+                        }
+                        next_page = next;
+                    },
+                    ddb_lib::MessageType::GetByAuthor { .. } => {}, // Explorer should not be asked this
+                    ddb_lib::MessageType::GetChanges { .. } => {}, // Explorer should not be asked this
+                    ddb_lib::MessageType::Changes { entries, next } => {
+                        for entry in entries {
+                            let _ = ui_in_tx.send(UiMessage::Message(format!("Change: {}={} at {} by {}", entry.key, entry.val, entry.seq, entry.id)));
+                        }
+                        if next.is_some() {
+                            let _ = ui_in_tx.send(UiMessage::Message("More changes follow, use `more` to see them".into()));
+                        }
+                        next_page = next.map(|next| next.to_string());
+                    },
+                    ddb_lib::MessageType::GetHeads { key: _ } => {}, // Explorer should not be asked this
+                    ddb_lib::MessageType::Heads { key, entries } => {
+                        if entries.is_empty() {
+                            let _ = ui_in_tx.send(UiMessage::Message(format!("{key} has no values")));
+                        } else if entries.len() > 1 {
+                            let _ = ui_in_tx.send(UiMessage::Message(format!("{key} has {} conflicting values:", entries.len())));
+                        }
+                        for entry in entries {
+                            let _ = ui_in_tx.send(UiMessage::Message(format!("Head: {key}={} by {} ({})", entry.val, entry.id, entry.hash())));
+                        }
+                    },
+                    ddb_lib::MessageType::Resolve { key: _ } => {}, // Explorer should not be asked this
+                    ddb_lib::MessageType::Resolved { key, winner, alternatives } => {
+                        match winner {
+                            Some(entry) => {let _ = ui_in_tx.send(UiMessage::Message(format!("Resolved: {key}={} by {}", entry.val, entry.id)));},
+                            None => {let _ = ui_in_tx.send(UiMessage::Message(format!("{key} has no values")));},
+                        }
+                        for entry in alternatives {
+                            let _ = ui_in_tx.send(UiMessage::Message(format!("Alternative: {key}={} by {}", entry.val, entry.id)));
+                        }
+                    },
+                    ddb_lib::MessageType::Set { .. } => {}, // Explorer does not store items
+                    ddb_lib::MessageType::Delete { key: _ } => {}, // Explorer does not store items
+                    ddb_lib::MessageType::Batch { .. } => {}, // Explorer does not store items
+                    ddb_lib::MessageType::CompareAndSet { .. } => {}, // Explorer does not store items
+                    ddb_lib::MessageType::Swapped { key, outcome, heads } => {
+                        let message = match outcome {
+                            SwapOutcome::Written => format!("Set {key}"),
+                            SwapOutcome::Changed => format!("{key} changed since it was read, not set"),
+                            SwapOutcome::Refused => format!("The node refused to set {key}"),
+                        };
+                        let _ = ui_in_tx.send(UiMessage::Message(message));
+                        for entry in heads {
+                            let _ = ui_in_tx.send(UiMessage::Message(format!("Head: {key}={} by {} ({})", entry.val, entry.id, entry.hash())));
+                        }
+                    },
+                    ddb_lib::MessageType::Grant(_chain) => {}, // Explorer does not write entries
                     ddb_lib::MessageType::Link(_addr) => {}, // Explorer does not link anywhere else
                     ddb_lib::MessageType::Neighbors(_neighbors) => {}, // Explorer has no neighbors except the node it connects to.
                     ddb_lib::MessageType::GetTrust => {}, // Explorer only trusts the one it is connected to
-                    ddb_lib::MessageType::Trust{of, delta } => {}, // Explorer does not hold any trust tables
+                    ddb_lib::MessageType::Trust{of: _, delta: _ } => {}, // Explorer does not hold any trust tables
+                    ddb_lib::MessageType::TrustLevel { .. } => {}, // Explorer does not hold any trust tables
+                    ddb_lib::MessageType::KeyEvent(_event) => {}, // Explorer does not hold any trust tables
                 }
                 // new message from the network
                 // should process it
@@ -161,31 +465,76 @@ fn main() -> Result<(), io::Error> {
     Ok(())
 }
 
+/// The explorer's link to a node.
+///
+/// The network is shared with the thread listening on it, which completes handshakes.
+struct Connection {
+    network: Arc<Mutex<Network>>,
+    node: SocketAddr,
+}
+
+impl Connection {
+    /// Sends a message to the node, verifying it first if needed
+    fn send(&self, msg: Message) {
+        self.network.lock().unwrap().send_addr(self.node, msg);
+    }
+}
+
 fn create_network_thread<A1: ToSocketAddrs, A2: ToSocketAddrs>(
     listen: A1,
     addr: A2,
-) -> Option<(UdpSocket, Receiver<(SocketAddr, Message)>)> {
-    let sock = UdpSocket::bind(listen).ok()?;
-    sock.connect(addr).expect("socket should connect");
-    let inner_sock = sock.try_clone().ok()?;
+    secret: SecretId,
+) -> Option<(Connection, Receiver<(SocketAddr, Message)>)> {
+    let node = addr.to_socket_addrs().ok()?.next()?;
+    let mut network = Network::new(listen, secret)?;
+    // the explorer is never anyone's neighbor
+    network.set_accepts_neighbors(false);
+    // wake regularly so the network is not held locked while waiting for messages
+    network.set_read_timeout(Some(Duration::from_millis(50)));
+    let network = Arc::new(Mutex::new(network));
+
     let (tx, rx) = channel::bounded(10);
-    thread::spawn(move || network_thread(inner_sock, tx));
-    Some((sock, rx))
+    let inner_network = network.clone();
+    thread::spawn(move || network_thread(inner_network, tx));
+    Some((Connection { network, node }, rx))
 }
 
-fn network_thread(sock: UdpSocket, tx: Sender<(SocketAddr, Message)>) -> Option<()> {
-    loop {
-        let mut buf = [0u8; 2000];
-        let (byte_count, from_addr) = sock.recv_from(&mut buf).ok()?;
-        let msg = Message::deserialize(&buf[..byte_count]).map(|msg| (from_addr, msg));
-        let Some(msg) = msg else { continue };
+fn network_thread(network: Arc<Mutex<Network>>, tx: Sender<(SocketAddr, Message)>) {
+    // stop once the connection has been dropped
+    while Arc::strong_count(&network) > 1 {
+        let mut net = network.lock().unwrap();
+        let Some((from_addr, msg)) = net.listen() else { continue };
+        let from = *msg.from();
+        match msg.msg_type() {
+            MessageType::Verify { challenge, stamp, key, addr } => {
+                net.verify(&from_addr, from, challenge.clone(), stamp, key, addr);
+            }
+            MessageType::Verified { challenge, is_neighbor, stamp, key, observed, proof } => {
+                net.verified(from, challenge, *is_neighbor, stamp, key, observed, proof);
+            }
+            _ => {
+                drop(net);
+                if tx.send((from_addr, msg)).is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
 
-        tx.send(msg);
+/// The command line after its first `words` words, with its spacing kept exactly
+fn rest_of_command(cmd: &str, words: usize) -> &str {
+    let mut rest = cmd;
+    for _ in 0..words {
+        // only the single space after the last word is dropped
+        rest = rest.trim_start_matches(' ');
+        rest = rest.split_once(' ').map_or("", |(_word, rest)| rest);
     }
+    rest
 }
 
 fn input_thread(tx: Sender<char>) {
-    while let Some(key) = std::io::stdin().bytes().next() {
+    while let Some(key) = std::io::stdin().lock().bytes().next() {
         if let Ok(key) = key {
             if let Some(char) = char::from_u32(key as u32) {
                 tx.send(char).expect("io should be open");
@@ -1,11 +1,7 @@
 use std::io;
 
 use crossbeam::channel::{Receiver, Sender};
-use crossterm::{
-    event::{DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture},
-    execute,
-    terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
-};
+use crossterm::{event::EnableBracketedPaste, execute, terminal::EnterAlternateScreen};
 use tui::{
     Terminal,
     backend::CrosstermBackend,
@@ -66,7 +62,7 @@ pub fn ui_thread(rx: Receiver<UiMessage>, tx: Sender<String>) -> Result<(), io::
                 }
                 '\r' | '\n' => {
                     history.push(format!(">{command}"));
-					tx.send(command);
+					let _ = tx.send(command);
                     command = String::new()
                 }
                 _ => {
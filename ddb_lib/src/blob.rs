@@ -0,0 +1,89 @@
+use std::fmt::Display;
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::Error};
+
+/// Arbitrary bytes with an optional content type, such as `image/png`.
+///
+/// Serialized as base64 in human readable formats like json, and as raw bytes on the wire.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub struct Blob {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    #[serde(with = "bytes")]
+    pub data: Vec<u8>,
+}
+
+impl Blob {
+    pub fn new(content_type: Option<String>, data: Vec<u8>) -> Self {
+        Self { content_type, data }
+    }
+
+    /// The data as text, if it is utf8 and not tagged as some other content type
+    pub fn as_text(&self) -> Option<&str> {
+        match self.content_type.as_deref() {
+            None => {}
+            Some(content_type) if content_type.starts_with("text/") => {}
+            Some(_) => return None,
+        }
+        std::str::from_utf8(&self.data).ok()
+    }
+}
+
+impl From<String> for Blob {
+    fn from(text: String) -> Self {
+        Self::new(None, text.into_bytes())
+    }
+}
+
+impl From<&str> for Blob {
+    fn from(text: &str) -> Self {
+        Self::from(text.to_string())
+    }
+}
+
+impl From<Vec<u8>> for Blob {
+    fn from(data: Vec<u8>) -> Self {
+        Self::new(None, data)
+    }
+}
+
+/// Text is shown as is, anything else as its content type, length and base64
+impl Display for Blob {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(text) = self.as_text() {
+            return write!(f, "{text}");
+        }
+        let content_type = self
+            .content_type
+            .as_deref()
+            .unwrap_or("application/octet-stream");
+        write!(
+            f,
+            "<{content_type}, {} bytes> {}",
+            self.data.len(),
+            STANDARD.encode(&self.data)
+        )
+    }
+}
+
+mod bytes {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&STANDARD.encode(data))
+        } else {
+            serde_bytes::Bytes::new(data).serialize(serializer)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        if deserializer.is_human_readable() {
+            let s = String::deserialize(deserializer)?;
+            STANDARD.decode(s).map_err(D::Error::custom)
+        } else {
+            serde_bytes::ByteBuf::deserialize(deserializer).map(|buf| buf.into_vec())
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use crate::id::{Id, SecretId, Signature};
+
+/// Longest delegation chain accepted, so checking an entry stays cheap
+pub const MAX_CHAIN: usize = 8;
+
+/// A grant, signed by `grantor`, letting `grantee` write keys starting with `prefix`
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub struct Capability {
+    pub grantor: Id,
+    pub grantee: Id,
+    pub prefix: String,
+    /// When the grant runs out, in milliseconds since the unix epoch like `SequenceNumber::time`
+    pub expiry: u64,
+    /// Whether the grantee may pass the grant on
+    pub delegable: bool,
+    pub sig: Signature,
+}
+
+/// The parts of a capability covered by its signature
+#[derive(serde::Serialize)]
+struct SignedFields<'a> {
+    grantor: &'a Id,
+    grantee: &'a Id,
+    prefix: &'a str,
+    expiry: u64,
+    delegable: bool,
+}
+
+impl Capability {
+    /// Grants `grantee` writes under `prefix` until `expiry`, signed by `secret`
+    pub fn new(secret: &SecretId, grantee: Id, prefix: String, expiry: u64, delegable: bool) -> Self {
+        let grantor = secret.id();
+        let sig = secret.sign(&signed_bytes(&grantor, &grantee, &prefix, expiry, delegable));
+        Self {
+            grantor,
+            grantee,
+            prefix,
+            expiry,
+            delegable,
+            sig,
+        }
+    }
+
+    /// Checks that the grant was made by its grantor and has not been altered since
+    pub fn verify(&self) -> bool {
+        let bytes = signed_bytes(&self.grantor, &self.grantee, &self.prefix, self.expiry, self.delegable);
+        self.grantor.verify(&bytes, &self.sig)
+    }
+
+    /// Checks that a delegation chain lets `author` write `key` at `time`.
+    ///
+    /// The chain starts with a grant by `owner`, and each later grant is made by the grantee
+    /// of the one before, which must be delegable, and can only narrow its prefix.
+    pub fn authorises(chain: &[Capability], owner: &Id, author: &Id, key: &str, time: u64) -> bool {
+        if chain.is_empty() || chain.len() > MAX_CHAIN {
+            return false;
+        }
+        let mut grantor = owner;
+        let mut prefix = "";
+        for (i, capability) in chain.iter().enumerate() {
+            let last = i + 1 == chain.len();
+            if capability.grantor != *grantor
+                || !capability.prefix.starts_with(prefix)
+                || capability.expiry < time
+                || !(last || capability.delegable)
+                || !capability.verify()
+            {
+                return false;
+            }
+            grantor = &capability.grantee;
+            prefix = &capability.prefix;
+        }
+        grantor == author && key.starts_with(prefix)
+    }
+}
+
+fn signed_bytes(grantor: &Id, grantee: &Id, prefix: &str, expiry: u64, delegable: bool) -> Vec<u8> {
+    let fields = SignedFields {
+        grantor,
+        grantee,
+        prefix,
+        expiry,
+        delegable,
+    };
+    serde_json::ser::to_vec(&fields).expect("should be serializable")
+}
@@ -0,0 +1,224 @@
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    str::FromStr,
+};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    capability::Capability,
+    id::{Id, SecretId, Signature, bytes},
+    message::{REPLY_BUDGET, encoded_len},
+    sequence_num::SequenceNumber,
+    value::Value,
+};
+
+/// Largest entry accepted once encoded, so a Got carrying both the merged value of a key and
+/// its newest entry fits in one message
+pub const MAX_ENTRY: usize = REPLY_BUDGET / 2;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub struct Entry {
+    pub id: Id,
+    pub seq: SequenceNumber,
+    pub key: String,
+    pub val: Value,
+    /// Hashes of the entries for the same key that this entry supersedes
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parents: Vec<EntryHash>,
+    /// Number of entries in the batch this entry was written in, 0 if it was written alone.
+    ///
+    /// The entries of a batch share their author and sequence number, and are only stored together.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub batch: u32,
+    /// When the entry expires and is no longer read, in milliseconds since the unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<u64>,
+    /// Signature by `id` over the rest of the entry, apart from `capabilities`
+    pub sig: Signature,
+    /// Delegation chain from the owner of a namespaced key to `id`, each grant carries its own signature
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub capabilities: Vec<Capability>,
+}
+
+/// The parts of an entry covered by its signature
+#[derive(serde::Serialize)]
+struct SignedFields<'a> {
+    id: &'a Id,
+    seq: &'a SequenceNumber,
+    key: &'a str,
+    val: &'a Value,
+    #[serde(skip_serializing_if = "<[EntryHash]>::is_empty")]
+    parents: &'a [EntryHash],
+    #[serde(skip_serializing_if = "is_zero")]
+    batch: &'a u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires: &'a Option<u64>,
+}
+
+impl Entry {
+    /// Creates an entry authored and signed by `secret`
+    pub fn new(secret: &SecretId, seq: SequenceNumber, key: String, val: Value) -> Self {
+        Self::with_parents(secret, seq, key, val, Vec::new())
+    }
+
+    /// Creates an entry that supersedes the entries with the given hashes
+    pub fn with_parents(
+        secret: &SecretId,
+        seq: SequenceNumber,
+        key: String,
+        val: Value,
+        parents: Vec<EntryHash>,
+    ) -> Self {
+        let id = secret.id();
+        let sig = secret.sign(&signed_bytes(&id, &seq, &key, &val, &parents, &0, &None));
+        Self {
+            id,
+            seq,
+            key,
+            val,
+            parents,
+            batch: 0,
+            expires: None,
+            sig,
+            capabilities: Vec::new(),
+        }
+    }
+
+    /// Attaches the delegation chain that lets the author write to another author's namespace
+    pub fn with_capabilities(mut self, capabilities: Vec<Capability>) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Makes `secret` the author of the entry and signs it, used after the entry has been changed
+    pub fn sign(&mut self, secret: &SecretId) {
+        self.id = secret.id();
+        self.sig = secret.sign(&self.signed_bytes());
+    }
+
+    /// Checks that the entry was written by its author and has not been altered since
+    pub fn verify(&self) -> bool {
+        self.id.verify(&self.signed_bytes(), &self.sig)
+    }
+
+    /// Checks that the author may write to the entry's key, as of `now` in milliseconds since the unix epoch.
+    ///
+    /// Namespaced keys belong to one author, and to those it granted writes to. The grants must
+    /// still hold at `now`, as the author chooses the entry's sequence number and could backdate it.
+    pub fn may_write(&self, now: u64) -> bool {
+        key_owner(&self.key).is_none_or(|owner| {
+            let time = now.max(self.seq.time);
+            owner == self.id || Capability::authorises(&self.capabilities, &owner, &self.id, &self.key, time)
+        })
+    }
+
+    /// Checks that the entry is small enough to be sent, see `MAX_ENTRY`
+    pub fn fits(&self) -> bool {
+        encoded_len(self) <= MAX_ENTRY
+    }
+
+    /// Checks whether the entry has expired by `now`, in milliseconds since the unix epoch
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    /// Identifies the entry, so later entries can name it as a parent
+    pub fn hash(&self) -> EntryHash {
+        EntryHash {
+            hash: Sha256::digest(self.signed_bytes()).into(),
+        }
+    }
+
+    /// Orders entries by sequence number, with the author breaking ties between concurrent writes
+    pub fn order(&self, other: &Self) -> Ordering {
+        self.seq.order(&other.seq).then_with(|| self.id.cmp(&other.id))
+    }
+
+    fn signed_bytes(&self) -> Vec<u8> {
+        signed_bytes(&self.id, &self.seq, &self.key, &self.val, &self.parents, &self.batch, &self.expires)
+    }
+
+    /// Drops the entries of batches that are not all present, so a batch is stored whole or not at all
+    pub fn retain_complete_batches(entries: &mut Vec<Entry>) {
+        let mut batches: HashMap<(Id, SequenceNumber), HashSet<&str>> = HashMap::new();
+        for entry in entries.iter().filter(|entry| entry.batch > 0) {
+            batches.entry((entry.id, entry.seq)).or_default().insert(&entry.key);
+        }
+        let present: HashMap<_, _> = batches
+            .into_iter()
+            .map(|(batch, keys)| (batch, keys.len()))
+            .collect();
+        entries.retain(|entry| entry.batch == 0 || present[&(entry.id, entry.seq)] == entry.batch as usize);
+    }
+}
+
+/// The author owning a namespaced key, written `<author-id>/path`.
+///
+/// Only the owner may write to a namespaced key, other keys are shared by every author.
+pub fn key_owner(key: &str) -> Option<Id> {
+    let (owner, _path) = key.split_once('/')?;
+    owner.parse().ok()
+}
+
+fn signed_bytes(
+    id: &Id,
+    seq: &SequenceNumber,
+    key: &str,
+    val: &Value,
+    parents: &[EntryHash],
+    batch: &u32,
+    expires: &Option<u64>,
+) -> Vec<u8> {
+    let fields = SignedFields {
+        id,
+        seq,
+        key,
+        val,
+        parents,
+        batch,
+        expires,
+    };
+    serde_json::ser::to_vec(&fields).expect("should be serializable")
+}
+
+fn is_zero(n: &u32) -> bool {
+    *n == 0
+}
+
+/// The sha256 hash of an entry's signed fields, serialized as lowercase hex in human readable formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EntryHash {
+    hash: [u8; 32],
+}
+
+impl Display for EntryHash {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", hex::encode(self.hash))
+    }
+}
+
+impl FromStr for EntryHash {
+    type Err = hex::FromHexError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut hash = [0u8; 32];
+        hex::decode_to_slice(s, &mut hash)?;
+        Ok(EntryHash { hash })
+    }
+}
+
+impl Serialize for EntryHash {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        bytes::serialize(&self.hash, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for EntryHash {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let hash = bytes::deserialize(deserializer)?;
+        Ok(EntryHash { hash })
+    }
+}
@@ -0,0 +1,301 @@
+use std::{fmt::Display, net::SocketAddr, str::FromStr, time::Instant};
+
+use chacha20poly1305::{
+    ChaCha20Poly1305, Key, Nonce,
+    aead::{Aead, KeyInit},
+};
+use hkdf::Hkdf;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::{
+    id::{Id, bytes},
+    message::Message,
+};
+
+/// Packets starting with this byte hold a plaintext message, only used for the handshake
+const PLAIN_TAG: u8 = 0;
+/// Packets starting with this byte hold a nonce followed by an encrypted message
+const SEALED_TAG: u8 = 1;
+/// Nonces are the sender's direction followed by its count of packets sealed before
+const NONCE_LENGTH: usize = 12;
+const DIRECTION_LENGTH: usize = NONCE_LENGTH - 8;
+/// Number of counters before the newest one still accepted, for packets delivered out of order
+const REPLAY_WINDOW: u64 = 64;
+/// Bytes a sealed packet adds to its message: the tag, the nonce and the authentication tag
+pub(crate) const SEAL_OVERHEAD: usize = 1 + NONCE_LENGTH + 16;
+
+/// An ephemeral X25519 public key exchanged in `Verify`/`Verified`, serialized as lowercase hex in human readable formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HandshakeKey {
+    key: [u8; 32],
+}
+
+impl Display for HandshakeKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", hex::encode(self.key))
+    }
+}
+
+impl FromStr for HandshakeKey {
+    type Err = hex::FromHexError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut key = [0u8; 32];
+        hex::decode_to_slice(s, &mut key)?;
+        Ok(HandshakeKey { key })
+    }
+}
+
+impl Serialize for HandshakeKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        bytes::serialize(&self.key, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for HandshakeKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let key = bytes::deserialize(deserializer)?;
+        Ok(HandshakeKey { key })
+    }
+}
+
+/// One side of a key exchange, created for each `Verify` challenge.
+///
+/// Both `Verify` and `Verified` are signed by their senders, so the exchanged keys are
+/// bound to the Ids of both nodes. The resulting session key is also bound to both Ids
+/// and the challenge.
+pub struct Handshake {
+    secret: StaticSecret,
+}
+
+impl Handshake {
+    pub fn new() -> Self {
+        Self {
+            secret: StaticSecret::from(rand::random::<[u8; 32]>()),
+        }
+    }
+
+    pub fn key(&self) -> HandshakeKey {
+        HandshakeKey {
+            key: PublicKey::from(&self.secret).to_bytes(),
+        }
+    }
+
+    /// Completes the exchange with the other node's key.
+    ///
+    /// `initiator` is the node that sent the `Verify` challenge.
+    /// Returns None if the other key would make the shared secret predictable.
+    pub fn finish(
+        self,
+        theirs: &HandshakeKey,
+        initiator: bool,
+        us: &Id,
+        them: &Id,
+        challenge: &str,
+    ) -> Option<Session> {
+        let ours = self.key();
+        let shared = self.secret.diffie_hellman(&PublicKey::from(theirs.key));
+        if !shared.was_contributory() {
+            return None;
+        }
+
+        let (initiator_id, responder_id, initiator_key, responder_key) = if initiator {
+            (us, them, &ours, theirs)
+        } else {
+            (them, us, theirs, &ours)
+        };
+        let mut info = b"ddb session".to_vec();
+        info.extend_from_slice(initiator_id.as_bytes());
+        info.extend_from_slice(responder_id.as_bytes());
+        info.extend_from_slice(&initiator_key.key);
+        info.extend_from_slice(&responder_key.key);
+
+        let mut key = [0u8; 32];
+        Hkdf::<Sha256>::new(Some(challenge.as_bytes()), shared.as_bytes())
+            .expand(&info, &mut key)
+            .expect("32 bytes is a valid hkdf output length");
+
+        Some(Session {
+            peer: *them,
+            cipher: ChaCha20Poly1305::new(Key::from_slice(&key)),
+            established: Instant::now(),
+            initiator,
+            sealed: 0,
+            opened: ReplayWindow::default(),
+        })
+    }
+}
+
+impl Default for Handshake {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Keys for encrypted, authenticated traffic with one peer.
+///
+/// Both directions share a key, so each side puts its own direction in the nonce along with
+/// a counter, and a packet is only opened once.
+pub struct Session {
+    peer: Id,
+    cipher: ChaCha20Poly1305,
+    established: Instant,
+    /// If we sent the challenge, which tells our direction apart from the peer's
+    initiator: bool,
+    /// Number of packets sealed so far, the counter of the next one
+    sealed: u64,
+    /// Counters of the packets opened so far
+    opened: ReplayWindow,
+}
+
+impl Session {
+    /// The Id of the node on the other end of the session
+    pub fn peer(&self) -> &Id {
+        &self.peer
+    }
+
+    pub fn established(&self) -> Instant {
+        self.established
+    }
+
+    /// Encrypts a message into a packet ready to be sent
+    pub fn seal(&mut self, msg: &Message) -> Vec<u8> {
+        let nonce = nonce(self.initiator, self.sealed);
+        self.sealed += 1;
+        let ciphertext = self
+            .cipher
+            .encrypt(Nonce::from_slice(&nonce), msg.serialize().as_slice())
+            .expect("encryption should succeed");
+
+        let mut packet = Vec::with_capacity(1 + NONCE_LENGTH + ciphertext.len());
+        packet.push(SEALED_TAG);
+        packet.extend_from_slice(&nonce);
+        packet.extend_from_slice(&ciphertext);
+        packet
+    }
+
+    /// Decrypts a sealed packet.
+    ///
+    /// Returns None if it was not sealed by the peer with this session, or was opened before.
+    pub fn open(&mut self, packet: &[u8]) -> Option<Message> {
+        let Packet::Sealed(data) = Packet::decode(packet)? else {
+            return None;
+        };
+        if data.len() < NONCE_LENGTH {
+            return None;
+        }
+        let (nonce, ciphertext) = data.split_at(NONCE_LENGTH);
+        let (direction, counter) = nonce.split_at(DIRECTION_LENGTH);
+        let counter = u64::from_be_bytes(counter.try_into().ok()?);
+        // our own packets reflected back, or ones already opened
+        if direction != direction_bytes(!self.initiator) || !self.opened.is_new(counter) {
+            return None;
+        }
+        let plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .ok()?;
+        self.opened.insert(counter);
+        Message::deserialize(&plaintext)
+    }
+}
+
+/// The nonce of the packet numbered `counter` sent by the initiator, or the responder
+fn nonce(initiator: bool, counter: u64) -> [u8; NONCE_LENGTH] {
+    let mut nonce = [0u8; NONCE_LENGTH];
+    nonce[..DIRECTION_LENGTH].copy_from_slice(&direction_bytes(initiator));
+    nonce[DIRECTION_LENGTH..].copy_from_slice(&counter.to_be_bytes());
+    nonce
+}
+
+fn direction_bytes(initiator: bool) -> [u8; DIRECTION_LENGTH] {
+    (initiator as u32).to_be_bytes()
+}
+
+/// The newest counter opened, and which of the `REPLAY_WINDOW` counters before it were opened
+#[derive(Default)]
+struct ReplayWindow {
+    newest: Option<u64>,
+    /// Bit `n` is set once the counter `n` before the newest has been opened
+    seen: u64,
+}
+
+impl ReplayWindow {
+    /// If a packet with this counter may still be opened
+    fn is_new(&self, counter: u64) -> bool {
+        let Some(newest) = self.newest else {
+            return true;
+        };
+        if counter > newest {
+            return true;
+        }
+        let age = newest - counter;
+        age < REPLAY_WINDOW && self.seen & (1 << age) == 0
+    }
+
+    fn insert(&mut self, counter: u64) {
+        match self.newest {
+            Some(newest) if counter <= newest => self.seen |= 1 << (newest - counter),
+            newest => {
+                let shift = newest.map_or(REPLAY_WINDOW, |newest| counter - newest);
+                let kept = if shift < REPLAY_WINDOW { self.seen << shift } else { 0 };
+                self.seen = kept | 1;
+                self.newest = Some(counter);
+            }
+        }
+    }
+}
+
+/// The parts of a handshake covered by the responder's proof in `Verified`
+#[derive(serde::Serialize)]
+struct ProofFields<'a> {
+    challenge: &'a str,
+    responder_addr: &'a SocketAddr,
+    requester_addr: &'a SocketAddr,
+    responder_key: &'a HandshakeKey,
+    requester_key: &'a HandshakeKey,
+}
+
+/// Bytes the responder signs to prove it owns its Id and answered a challenge sent to `responder_addr`
+pub(crate) fn proof_bytes(
+    challenge: &str,
+    responder_addr: &SocketAddr,
+    requester_addr: &SocketAddr,
+    responder_key: &HandshakeKey,
+    requester_key: &HandshakeKey,
+) -> Vec<u8> {
+    let fields = ProofFields {
+        challenge,
+        responder_addr,
+        requester_addr,
+        responder_key,
+        requester_key,
+    };
+    serde_json::ser::to_vec(&fields).expect("should be serializable")
+}
+
+/// A packet as received from the network
+pub(crate) enum Packet<'a> {
+    Plain(Box<Message>),
+    /// Nonce and ciphertext of a sealed message
+    Sealed(&'a [u8]),
+}
+
+impl<'a> Packet<'a> {
+    pub(crate) fn decode(data: &'a [u8]) -> Option<Self> {
+        let (tag, rest) = data.split_first()?;
+        match *tag {
+            PLAIN_TAG => Message::deserialize(rest).map(|msg| Packet::Plain(Box::new(msg))),
+            SEALED_TAG => Some(Packet::Sealed(rest)),
+            _ => None,
+        }
+    }
+
+    /// Encodes a plaintext packet
+    pub(crate) fn plain(msg: &Message) -> Vec<u8> {
+        let mut packet = vec![PLAIN_TAG];
+        packet.extend_from_slice(&msg.serialize());
+        packet
+    }
+}
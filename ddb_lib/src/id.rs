@@ -1,43 +1,209 @@
-use std::{fmt::Display, num::ParseIntError, str::FromStr};
+use std::{fmt::Display, str::FromStr};
 
-#[derive(
-    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
-)]
+use ed25519_dalek::{Signer, SigningKey, VerifyingKey};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::Error};
+
+/// Length in bytes of an encoded public key
+pub const ID_LENGTH: usize = 32;
+/// Length in bytes of an encoded signature
+pub const SIGNATURE_LENGTH: usize = 64;
+
+/// The public identity of a node: an Ed25519 public key.
+///
+/// Displayed and parsed as lowercase hex, and sent as raw bytes on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Id {
-    // some sort of id/asymmetric key
-    tmp: u16,
+    key: [u8; ID_LENGTH],
 }
 
 impl Id {
-    pub fn generate() -> Self {
-        Self {
-            tmp: rand::random(),
-        }
+    pub fn as_bytes(&self) -> &[u8; ID_LENGTH] {
+        &self.key
+    }
+
+    /// Checks that `sig` was produced over `data` by the secret key matching this Id
+    pub fn verify(&self, data: &[u8], sig: &Signature) -> bool {
+        let Ok(key) = VerifyingKey::from_bytes(&self.key) else {
+            return false;
+        };
+        let sig = ed25519_dalek::Signature::from_bytes(&sig.bytes);
+        key.verify_strict(data, &sig).is_ok()
     }
 }
 
 impl Display for Id {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.tmp)
+        write!(f, "{}", hex::encode(self.key))
     }
 }
 
 impl FromStr for Id {
-	type Err = ParseIntError;
+    type Err = ParseIdError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-		let inner = s.parse::<u16>()?;
-		Ok(Id { tmp: inner })
-	}
+        let mut key = [0u8; ID_LENGTH];
+        hex::decode_to_slice(s, &mut key).map_err(|_| ParseIdError)?;
+        // reject strings that are not points on the curve
+        VerifyingKey::from_bytes(&key).map_err(|_| ParseIdError)?;
+        Ok(Id { key })
+    }
 }
 
+/// Placeholder identity used before a real one is known.
+///
+/// The all zero key is a weak key, so nothing will ever verify against it.
 impl Default for Id {
-	fn default() -> Self {
-		Self { tmp: 12345 }
-	}
+    fn default() -> Self {
+        Self {
+            key: [0; ID_LENGTH],
+        }
+    }
+}
+
+impl From<VerifyingKey> for Id {
+    fn from(value: VerifyingKey) -> Self {
+        Self {
+            key: value.to_bytes(),
+        }
+    }
+}
+
+impl Serialize for Id {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        bytes::serialize(&self.key, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Id {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let key = bytes::deserialize(deserializer)?;
+        // reject keys that are not points on the curve
+        VerifyingKey::from_bytes(&key).map_err(|_| D::Error::custom(ParseIdError))?;
+        Ok(Id { key })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseIdError;
+
+impl Display for ParseIdError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "id should be {ID_LENGTH} hex encoded bytes of a public key")
+    }
+}
+
+impl std::error::Error for ParseIdError {}
+
+/// The secret half of an identity, used to sign on behalf of its `Id`.
+///
+/// Displayed and parsed as lowercase hex, so keep it out of logs.
+#[derive(Clone)]
+pub struct SecretId {
+    key: SigningKey,
+}
+
+impl SecretId {
+    pub fn generate() -> Self {
+        Self {
+            key: SigningKey::from_bytes(&rand::random()),
+        }
+    }
+
+    pub fn id(&self) -> Id {
+        self.key.verifying_key().into()
+    }
+
+    pub fn sign(&self, data: &[u8]) -> Signature {
+        Signature {
+            bytes: self.key.sign(data).to_bytes(),
+        }
+    }
+
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.key.to_bytes())
+    }
+}
+
+impl std::fmt::Debug for SecretId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SecretId").field("id", &self.id()).finish()
+    }
+}
+
+impl FromStr for SecretId {
+    type Err = ParseIdError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut key = [0u8; ID_LENGTH];
+        hex::decode_to_slice(s.trim(), &mut key).map_err(|_| ParseIdError)?;
+        Ok(SecretId {
+            key: SigningKey::from_bytes(&key),
+        })
+    }
+}
+
+/// An Ed25519 signature, serialized as lowercase hex in human readable formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Signature {
+    bytes: [u8; SIGNATURE_LENGTH],
+}
+
+impl Signature {
+    pub fn as_bytes(&self) -> &[u8; SIGNATURE_LENGTH] {
+        &self.bytes
+    }
+}
+
+impl Display for Signature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", hex::encode(self.bytes))
+    }
+}
+
+impl FromStr for Signature {
+    type Err = hex::FromHexError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut bytes = [0u8; SIGNATURE_LENGTH];
+        hex::decode_to_slice(s, &mut bytes)?;
+        Ok(Signature { bytes })
+    }
+}
+
+impl Serialize for Signature {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        bytes::serialize(&self.bytes, serializer)
+    }
 }
 
-impl From<u16> for Id {
-    fn from(value: u16) -> Self {
-        Self { tmp: value }
+impl<'de> Deserialize<'de> for Signature {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let bytes = bytes::deserialize(deserializer)?;
+        Ok(Signature { bytes })
     }
-}
\ No newline at end of file
+}
+
+/// Fixed length bytes, serialized as lowercase hex in human readable formats like json, and as raw bytes on the wire
+pub(crate) mod bytes {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&hex::encode(bytes))
+        } else {
+            serde_bytes::Bytes::new(bytes).serialize(serializer)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, const N: usize>(deserializer: D) -> Result<[u8; N], D::Error> {
+        let mut bytes = [0u8; N];
+        if deserializer.is_human_readable() {
+            let s = String::deserialize(deserializer)?;
+            hex::decode_to_slice(s, &mut bytes).map_err(D::Error::custom)?;
+        } else {
+            let buf = serde_bytes::ByteBuf::deserialize(deserializer)?;
+            if buf.len() != N {
+                return Err(D::Error::invalid_length(buf.len(), &"a fixed number of bytes"));
+            }
+            bytes.copy_from_slice(&buf);
+        }
+        Ok(bytes)
+    }
+}
@@ -0,0 +1,80 @@
+use crate::id::{Id, SecretId, Signature};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum KeyChange {
+    /// The key is retired in favour of `successor`, which inherits its trust.
+    ///
+    /// `accepted` is the successor's signature agreeing to take over, so trust cannot be handed
+    /// to a key that never asked for it.
+    Rotate { successor: Id, accepted: Signature },
+    /// The key should no longer be trusted, and neither should anything it wrote
+    Revoke,
+}
+
+/// An announcement, signed by `id`'s own key, that the key is being retired
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub struct KeyEvent {
+    pub id: Id,
+    pub change: KeyChange,
+    pub sig: Signature,
+}
+
+/// The parts of a key event covered by its signature
+#[derive(serde::Serialize)]
+struct SignedFields<'a> {
+    id: &'a Id,
+    change: &'a KeyChange,
+}
+
+/// The parts of a rotation covered by the successor's signature
+#[derive(serde::Serialize)]
+struct AcceptedFields<'a> {
+    id: &'a Id,
+    successor: &'a Id,
+}
+
+impl KeyEvent {
+    /// Announces that `old` is replaced by the key `successor`, signed by both
+    pub fn rotate(old: &SecretId, successor: &SecretId) -> Self {
+        let accepted = successor.sign(&accepted_bytes(&old.id(), &successor.id()));
+        Self::new(
+            old,
+            KeyChange::Rotate {
+                successor: successor.id(),
+                accepted,
+            },
+        )
+    }
+
+    /// Announces that `secret` should no longer be trusted
+    pub fn revoke(secret: &SecretId) -> Self {
+        Self::new(secret, KeyChange::Revoke)
+    }
+
+    fn new(secret: &SecretId, change: KeyChange) -> Self {
+        let id = secret.id();
+        let sig = secret.sign(&signed_bytes(&id, &change));
+        Self { id, change, sig }
+    }
+
+    /// Checks that the event was announced by the key it retires, and accepted by its successor if any
+    pub fn verify(&self) -> bool {
+        let accepted = match &self.change {
+            KeyChange::Rotate { successor, accepted } => {
+                successor.verify(&accepted_bytes(&self.id, successor), accepted)
+            }
+            KeyChange::Revoke => true,
+        };
+        accepted && self.id.verify(&signed_bytes(&self.id, &self.change), &self.sig)
+    }
+}
+
+fn signed_bytes(id: &Id, change: &KeyChange) -> Vec<u8> {
+    let fields = SignedFields { id, change };
+    serde_json::ser::to_vec(&fields).expect("should be serializable")
+}
+
+fn accepted_bytes(id: &Id, successor: &Id) -> Vec<u8> {
+    let fields = AcceptedFields { id, successor };
+    serde_json::ser::to_vec(&fields).expect("should be serializable")
+}
@@ -1,9 +1,25 @@
+mod capability;
+pub use capability::{Capability, MAX_CHAIN};
+mod entry;
+pub use entry::{Entry, EntryHash, MAX_ENTRY, key_owner};
+mod key_event;
+pub use key_event::{KeyChange, KeyEvent};
 mod message;
-pub use message::{Entry, Message, MessageType};
+pub use message::{Message, MessageType, REPLY_BUDGET, SwapOutcome, encoded_len};
 mod id;
-pub use id::Id;
+pub use id::{Id, ParseIdError, SecretId, Signature};
 mod sequence_num;
-pub use sequence_num::SequenceNumber;
+pub use sequence_num::{Clock, SequenceNumber};
 
+mod handshake;
+pub use handshake::{Handshake, HandshakeKey, Session};
+mod stamp;
+pub use stamp::Stamp;
+mod blob;
+pub use blob::Blob;
+mod value;
+pub use value::{State, Value};
 mod network;
-pub use network::Network;
+pub use network::{MAX_MESSAGE, MAX_PACKET, Network};
+mod store;
+pub use store::{Cursor, MemoryStore, ParseCursorError, Store, Trust};
@@ -1,28 +1,103 @@
-use crate::{id::Id, sequence_num::SequenceNumber};
+use std::{
+    net::SocketAddr,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
-pub struct Entry {
-    pub id: Id,
-    pub seq: SequenceNumber,
-    pub key: String,
-    pub val: String,
+use crate::{
+    capability::Capability,
+    entry::{Entry, EntryHash},
+    handshake::HandshakeKey,
+    id::{Id, SecretId, Signature},
+    key_event::KeyEvent,
+    network::MAX_MESSAGE,
+    stamp::Stamp,
+    store::Cursor,
+    value::{State, Value},
+};
+
+/// Most bytes of entries or keys carried by a reply, leaving room for the rest of the message
+pub const REPLY_BUDGET: usize = MAX_MESSAGE - 1024;
+
+/// Length of anything once encoded in the wire format, for keeping messages within `MAX_MESSAGE`
+pub fn encoded_len<T: serde::Serialize>(value: &T) -> usize {
+    rmp_serde::to_vec_named(value).map_or(usize::MAX, |data| data.len())
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
 pub struct Message {
     from: Id,
     msg_type: MessageType,
+    /// When the message was signed, in milliseconds since the unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sent: Option<u64>,
+    /// Signature by `from` over the sender, send time and message type
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sig: Option<Signature>,
+}
+
+/// The parts of a message covered by its signature
+#[derive(serde::Serialize)]
+struct SignedFields<'a> {
+    from: &'a Id,
+    sent: u64,
+    msg_type: &'a MessageType,
 }
 
 impl Message {
+    /// Decodes a message in the wire format, MessagePack
     pub fn deserialize(data: &[u8]) -> Option<Self> {
-        serde_json::de::from_slice(&data).ok()
+        rmp_serde::from_slice(data).ok()
     }
 
+    /// The claimed sender of the message.
+    ///
+    /// This is only proven if `is_signed` returns true.
     pub fn from(&self) -> &Id {
         &self.from
     }
 
+    /// When the message was signed, in milliseconds since the unix epoch
+    pub fn sent(&self) -> Option<u64> {
+        self.sent
+    }
+
+    pub fn signature(&self) -> Option<&Signature> {
+        self.sig.as_ref()
+    }
+
+    /// Signs the message as sent now by `secret`, replacing its `from` field
+    pub fn signed(self, secret: &SecretId) -> Self {
+        let sent = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_millis() as u64);
+        self.signed_at(secret, sent)
+    }
+
+    /// Signs the message as sent by `secret` at `sent`, in milliseconds since the unix epoch
+    pub fn signed_at(mut self, secret: &SecretId, sent: u64) -> Self {
+        self.from = secret.id();
+        self.sent = Some(sent);
+        self.sig = Some(secret.sign(&self.signed_bytes(sent)));
+        self
+    }
+
+    /// Checks that the message carries a valid signature from its `from` Id
+    pub fn is_signed(&self) -> bool {
+        match (self.sent, &self.sig) {
+            (Some(sent), Some(sig)) => self.from.verify(&self.signed_bytes(sent), sig),
+            _ => false,
+        }
+    }
+
+    fn signed_bytes(&self, sent: u64) -> Vec<u8> {
+        let fields = SignedFields {
+            from: &self.from,
+            sent,
+            msg_type: &self.msg_type,
+        };
+        serde_json::ser::to_vec(&fields).expect("should be serializable")
+    }
+
     pub fn msg_type(&self) -> &MessageType {
         &self.msg_type
     }
@@ -35,6 +110,8 @@ impl Message {
         Self {
             from,
             msg_type: MessageType::Get { key, count },
+            sent: None,
+            sig: None,
         }
     }
 
@@ -42,34 +119,195 @@ impl Message {
         Self {
             from,
             msg_type: MessageType::Values(entries),
+            sent: None,
+            sig: None,
         }
     }
 
-    pub fn set(from: Id, key: String, val: String) -> Self {
-        // TODO: sequence number is not really needed here, the node will determine the next one
-        let entry = Entry {
-            id: from.clone(),
-            key,
-            val,
-            seq: SequenceNumber::ZERO,
-        };
+    pub fn get_heads(from: Id, key: String) -> Self {
+        Self {
+            from,
+            msg_type: MessageType::GetHeads { key },
+            sent: None,
+            sig: None,
+        }
+    }
+
+    pub fn heads(from: Id, key: String, entries: Vec<Entry>) -> Self {
+        Self {
+            from,
+            msg_type: MessageType::Heads { key, entries },
+            sent: None,
+            sig: None,
+        }
+    }
+
+    pub fn resolve(from: Id, key: String) -> Self {
+        Self {
+            from,
+            msg_type: MessageType::Resolve { key },
+            sent: None,
+            sig: None,
+        }
+    }
+
+    pub fn resolved(from: Id, key: String, winner: Option<Entry>, alternatives: Vec<Entry>) -> Self {
+        Self {
+            from,
+            msg_type: MessageType::Resolved {
+                key,
+                winner,
+                alternatives,
+            },
+            sent: None,
+            sig: None,
+        }
+    }
+
+    pub fn got(from: Id, key: String, state: Option<State>, entries: Vec<Entry>) -> Self {
+        Self {
+            from,
+            msg_type: MessageType::Got {
+                key,
+                state,
+                entries,
+            },
+            sent: None,
+            sig: None,
+        }
+    }
+
+    pub fn list_keys(from: Id, prefix: String, after: Option<String>, limit: usize) -> Self {
+        Self {
+            from,
+            msg_type: MessageType::ListKeys { prefix, after, limit },
+            sent: None,
+            sig: None,
+        }
+    }
+
+    pub fn keys(from: Id, keys: Vec<String>, next: Option<String>) -> Self {
+        Self {
+            from,
+            msg_type: MessageType::Keys { keys, next },
+            sent: None,
+            sig: None,
+        }
+    }
+
+    pub fn scan(from: Id, start: String, end: Option<String>, after: Option<String>, limit: usize) -> Self {
+        Self {
+            from,
+            msg_type: MessageType::Scan {
+                start,
+                end,
+                after,
+                limit,
+            },
+            sent: None,
+            sig: None,
+        }
+    }
+
+    pub fn scanned(from: Id, entries: Vec<Entry>, next: Option<String>) -> Self {
+        Self {
+            from,
+            msg_type: MessageType::Scanned { entries, next },
+            sent: None,
+            sig: None,
+        }
+    }
+
+    pub fn get_by_author(from: Id, author: Id, after: Option<Cursor>, limit: usize) -> Self {
+        Self {
+            from,
+            msg_type: MessageType::GetByAuthor { author, after, limit },
+            sent: None,
+            sig: None,
+        }
+    }
+
+    pub fn get_changes(from: Id, key: String, after: Option<Cursor>, limit: usize) -> Self {
+        Self {
+            from,
+            msg_type: MessageType::GetChanges { key, after, limit },
+            sent: None,
+            sig: None,
+        }
+    }
+
+    pub fn changes(from: Id, entries: Vec<Entry>, next: Option<Cursor>) -> Self {
+        Self {
+            from,
+            msg_type: MessageType::Changes { entries, next },
+            sent: None,
+            sig: None,
+        }
+    }
+
+    pub fn set(from: Id, key: String, val: Value) -> Self {
+        Self {
+            from,
+            msg_type: MessageType::Set { key, val, ttl: None },
+            sent: None,
+            sig: None,
+        }
+    }
+
+    /// Sets a value that expires `ttl` milliseconds after it is written
+    pub fn set_expiring(from: Id, key: String, val: Value, ttl: u64) -> Self {
         Self {
             from,
-            msg_type: MessageType::Set(entry),
+            msg_type: MessageType::Set { key, val, ttl: Some(ttl) },
+            sent: None,
+            sig: None,
         }
     }
 
-    pub fn verify(from: Id, challenge: String) -> Message {
+    pub fn verify(from: Id, challenge: String, stamp: Stamp, key: HandshakeKey, addr: SocketAddr) -> Message {
         Message {
             from,
-            msg_type: MessageType::Verify(challenge, [0; 16]),
+            msg_type: MessageType::Verify {
+                challenge,
+                stamp,
+                key,
+                addr,
+            },
+            sent: None,
+            sig: None,
         }
     }
 
-    pub fn verified(from: Id, challenge: String, can_be_neighbor: bool) -> Message {
+    pub fn verified(
+        from: Id,
+        challenge: String,
+        can_be_neighbor: bool,
+        stamp: Stamp,
+        key: HandshakeKey,
+        observed: SocketAddr,
+        proof: Signature,
+    ) -> Message {
         Message {
             from,
-            msg_type: MessageType::Verified(challenge, can_be_neighbor),
+            msg_type: MessageType::Verified {
+                challenge,
+                is_neighbor: can_be_neighbor,
+                stamp,
+                key,
+                observed,
+                proof,
+            },
+            sent: None,
+            sig: None,
+        }
+    }
+
+    pub fn delete(from: Id, key: String) -> Self {
+        Self {
+            from,
+            msg_type: MessageType::Delete { key },
+            sent: None,
+            sig: None,
         }
     }
 
@@ -77,6 +315,8 @@ impl Message {
         Message {
             from,
             msg_type: MessageType::Link(addr),
+            sent: None,
+            sig: None,
         }
     }
 
@@ -84,12 +324,16 @@ impl Message {
         Message {
             from,
             msg_type: MessageType::Neighbors(addrs),
+            sent: None,
+            sig: None,
         }
     }
     pub fn get_trust(from: Id) -> Message {
         Message {
             from,
             msg_type: MessageType::GetTrust,
+            sent: None,
+            sig: None,
         }
     }
 
@@ -97,11 +341,68 @@ impl Message {
         Message {
             from,
             msg_type: MessageType::Trust{of: target_id, delta},
+            sent: None,
+            sig: None,
+        }
+    }
+
+    pub fn trust_level(from: Id, of: Id, level: i16) -> Message {
+        Message {
+            from,
+            msg_type: MessageType::TrustLevel { of, level },
+            sent: None,
+            sig: None,
+        }
+    }
+
+    pub fn key_event(from: Id, event: KeyEvent) -> Message {
+        Message {
+            from,
+            msg_type: MessageType::KeyEvent(event),
+            sent: None,
+            sig: None,
         }
     }
 
+    pub fn batch(from: Id, writes: Vec<(String, Value)>) -> Message {
+        Message {
+            from,
+            msg_type: MessageType::Batch { writes },
+            sent: None,
+            sig: None,
+        }
+    }
+
+    pub fn compare_and_set(from: Id, key: String, expected: Option<EntryHash>, val: Value) -> Message {
+        Message {
+            from,
+            msg_type: MessageType::CompareAndSet { key, expected, val },
+            sent: None,
+            sig: None,
+        }
+    }
+
+    pub fn swapped(from: Id, key: String, outcome: SwapOutcome, heads: Vec<Entry>) -> Message {
+        Message {
+            from,
+            msg_type: MessageType::Swapped { key, outcome, heads },
+            sent: None,
+            sig: None,
+        }
+    }
+
+    pub fn grant(from: Id, chain: Vec<Capability>) -> Message {
+        Message {
+            from,
+            msg_type: MessageType::Grant(chain),
+            sent: None,
+            sig: None,
+        }
+    }
+
+    /// Encodes the message in the wire format, MessagePack with named fields
     pub fn serialize(&self) -> Vec<u8> {
-        serde_json::ser::to_vec(self).expect("should be serializable")
+        rmp_serde::to_vec_named(self).expect("should be serializable")
     }
 }
 
@@ -109,16 +410,37 @@ impl Message {
 pub enum MessageType {
     /// Before a message can be sent, a node should respond to a Verify challenge.
     /// This is to ensure that messages are sent to valid, active nodes.
-    /// 
-    /// First String is the challenge
-    /// Second array of u8s is padding
-    Verify(String, [u8; 16]),
+    ///
+    /// `stamp` is proof-of-work for the sender's Id, nodes will not become neighbors
+    /// with Ids whose stamp does not meet their difficulty.
+    /// The challenge also starts a key exchange, `key` is the sender's half of it.
+    /// `addr` is the address the challenge was sent to.
+    /// Verify is sent in plaintext and must be signed by its sender.
+    Verify {
+        challenge: String,
+        stamp: Stamp,
+        key: HandshakeKey,
+        addr: SocketAddr,
+    },
 
     /// Response to a Verify challenge
-    /// 
-    /// String is the returned challenge.
-    /// bool is if this node should be considered a neighbor of the other node
-    Verified(String, bool),
+    ///
+    /// `challenge` is the returned challenge.
+    /// `is_neighbor` is if this node should be considered a neighbor of the other node.
+    /// `stamp` is proof-of-work for the responder's Id.
+    /// `key` completes the key exchange, after which all messages are encrypted.
+    /// `observed` is the address the challenge came from.
+    /// `proof` is the responder's signature over the challenge, both addresses and both keys,
+    /// proving that the responder's Id is reachable at the challenged address.
+    /// Verified is sent in plaintext and must be signed by its sender.
+    Verified {
+        challenge: String,
+        is_neighbor: bool,
+        stamp: Stamp,
+        key: HandshakeKey,
+        observed: SocketAddr,
+        proof: Signature,
+    },
 
     /// Request the values for some key
     Get {
@@ -126,11 +448,147 @@ pub enum MessageType {
         count: usize,
     },
 
-    /// The returned entries for a Get request
+    /// The merged state of a key and its newest entries, returned for a Get request
+    Got {
+        key: String,
+        state: Option<State>,
+        entries: Vec<Entry>,
+    },
+
+    /// New entries, pushed across the network
     Values(Vec<Entry>),
 
-    /// Set the entry in the data
-    Set(Entry),
+    /// Request the keys starting with `prefix`, in order.
+    ///
+    /// At most `limit` keys are returned, starting after the key `after` if given.
+    ListKeys {
+        prefix: String,
+        after: Option<String>,
+        limit: usize,
+    },
+
+    /// The returned keys for a ListKeys request.
+    ///
+    /// `next` is set if more keys follow, and should be sent as `after` to get them.
+    Keys {
+        keys: Vec<String>,
+        next: Option<String>,
+    },
+
+    /// Request the winning entry of each key, picked like Resolve, from `start` up to, but not including, `end`.
+    ///
+    /// Paginated like ListKeys.
+    Scan {
+        start: String,
+        end: Option<String>,
+        after: Option<String>,
+        limit: usize,
+    },
+
+    /// The returned entries for a Scan request, paginated like Keys
+    Scanned {
+        entries: Vec<Entry>,
+        next: Option<String>,
+    },
+
+    /// Request the entries written by `author`, oldest first.
+    ///
+    /// At most `limit` entries are returned, starting after the cursor `after` if given.
+    GetByAuthor {
+        author: Id,
+        after: Option<Cursor>,
+        limit: usize,
+    },
+
+    /// Request the entries for a key, including deletions, oldest first. Paginated like GetByAuthor.
+    GetChanges {
+        key: String,
+        after: Option<Cursor>,
+        limit: usize,
+    },
+
+    /// The returned entries for a GetByAuthor or GetChanges request.
+    ///
+    /// `next` is set if more entries follow, and should be sent as `after` to get them.
+    Changes {
+        entries: Vec<Entry>,
+        next: Option<Cursor>,
+    },
+
+    /// Request the entries for a key that have not been superseded
+    GetHeads {
+        key: String,
+    },
+
+    /// The returned entries for a GetHeads request, more than one means the key has conflicting values
+    Heads {
+        key: String,
+        entries: Vec<Entry>,
+    },
+
+    /// Request a single value for a key, picked from its heads by the trust of their authors
+    Resolve {
+        key: String,
+    },
+
+    /// The returned value for a Resolve request, and the competing values it was picked over
+    Resolved {
+        key: String,
+        winner: Option<Entry>,
+        alternatives: Vec<Entry>,
+    },
+
+    /// Set the value of a key in the data.
+    ///
+    /// The node assigns the sequence number and signs the resulting entry itself.
+    /// The entry supersedes every head the node holds for the key.
+    /// The node fills in which adds a `SetRemove` has observed.
+    /// With a `ttl`, in milliseconds, the entry expires that long after it is written.
+    Set {
+        key: String,
+        val: Value,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ttl: Option<u64>,
+    },
+
+    /// Delete a key from the data.
+    ///
+    /// The node writes a tombstone entry, which hides every earlier entry for the key.
+    Delete {
+        key: String,
+    },
+
+    /// Set the values of several keys together.
+    ///
+    /// The node writes them as one batch of entries with the same sequence number, which are
+    /// stored and gossiped as a unit, so other nodes take all of them or none.
+    /// If a key is given more than once, its last value is written.
+    Batch {
+        writes: Vec<(String, Value)>,
+    },
+
+    /// Set the value of a key like Set, but only if the node's heads for it are still the
+    /// single entry `expected`, or the key has no value if `expected` is None.
+    ///
+    /// Lets a client read, modify and write a key without losing updates made in between.
+    /// The node replies with Swapped.
+    CompareAndSet {
+        key: String,
+        expected: Option<EntryHash>,
+        val: Value,
+    },
+
+    /// The outcome of a CompareAndSet, with the key's heads afterwards so a client can retry from them
+    Swapped {
+        key: String,
+        outcome: SwapOutcome,
+        heads: Vec<Entry>,
+    },
+
+    /// A delegation chain letting the receiving node write under another author's namespace.
+    ///
+    /// The node attaches it to the entries it writes there, see `Capability::authorises`.
+    Grant(Vec<Capability>),
 
     /// Attempt to connect to the following address
     Link(String),
@@ -144,4 +602,23 @@ pub enum MessageType {
 
     /// Change the node's trust in an Id by the given number of ten thousandths
     Trust{of: Id, delta: i16},
+
+    /// Reply to GetTrust, how much the sender trusts an Id in ten thousandths.
+    ///
+    /// Kept apart from Trust so a signed reply can never be replayed as a control message.
+    TrustLevel { of: Id, level: i16 },
+
+    /// A key announcing its successor or its revocation, gossiped to the whole network
+    KeyEvent(KeyEvent),
+}
+
+/// How a CompareAndSet turned out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum SwapOutcome {
+    /// The value was written
+    Written,
+    /// The heads were not the expected ones, so nothing was written
+    Changed,
+    /// The sender may not control the node, or the node may not write the key, so nothing was written
+    Refused,
 }
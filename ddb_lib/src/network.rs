@@ -1,7 +1,7 @@
 use std::{
     collections::{HashMap, hash_map::Entry},
     net::{SocketAddr, ToSocketAddrs, UdpSocket},
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use rand::{
@@ -10,12 +10,26 @@ use rand::{
     seq::{IndexedRandom, SliceRandom},
 };
 
-use crate::{Id, message::Message};
+use crate::{
+    Id, MessageType, SecretId, Signature, Stamp,
+    handshake::{Handshake, HandshakeKey, Packet, SEAL_OVERHEAD, Session, proof_bytes},
+    message::Message,
+};
 
 const VERIFICATION_TIMEOUT: Duration = Duration::from_secs(10 * 60); // 10 mins
 const CHALLENGE_TIMEOUT: Duration = Duration::from_secs(16);
 const PENDING_TIMEOUT: Duration = CHALLENGE_TIMEOUT;
 const REBROADCAST_TIMEOUT: Duration = Duration::from_secs(60);
+/// How far the send time of a `Verify` or `Verified` may be from our clock, so old ones cannot be replayed
+const HANDSHAKE_WINDOW: Duration = Duration::from_secs(60);
+/// Number of sessions kept per addr, so messages sealed with the previous session can
+/// still be opened while both sides switch to a new one
+const SESSIONS_PER_ADDR: usize = 2;
+
+/// Largest packet that fits in a single udp datagram, messages must be smaller once sealed
+pub const MAX_PACKET: usize = 65_507;
+/// Largest serialized message that still fits in a packet once sealed
+pub const MAX_MESSAGE: usize = MAX_PACKET - SEAL_OVERHEAD;
 
 /// Number of connections to try to have
 ///
@@ -24,22 +38,42 @@ const TARGET_CONNECTIONS: usize = 10;
 
 pub struct Network {
     id: Id,
+    secret: SecretId,
     sock: UdpSocket,
+    /// If we offer to be a neighbor to nodes that verify us
+    accepts_neighbors: bool,
+    /// Bits of proof-of-work required from a node's stamp before it can be our neighbor
+    difficulty: u32,
+    /// Our own proof-of-work, minted to our difficulty
+    stamp: Stamp,
     // keep list of verified addrs (verified addrs have replied with their key to prevent reflection attacks)
     // bool is if this addr is considered a neighbor
     verified_addrs: HashMap<SocketAddr, (Instant, bool)>,
-    challenges: HashMap<String, SocketAddr>,
+    /// Our outstanding challenges, with the addr they were sent to and our half of the key exchange
+    challenges: HashMap<String, (SocketAddr, Handshake, Instant)>,
+    /// Encryption sessions by addr, newest last
+    sessions: HashMap<SocketAddr, Vec<Session>>,
+    /// Ids proven to be reachable at verified addrs, and the reverse
+    peer_ids: HashMap<SocketAddr, Id>,
+    peer_addrs: HashMap<Id, SocketAddr>,
     pending: HashMap<SocketAddr, Vec<(Message, Instant)>>,
     recent_broadcasts: HashMap<Message, Instant>,
 }
 
 impl Network {
-    pub fn new<A: ToSocketAddrs>(addrs: A, id: Id) -> Option<Self> {
+    pub fn new<A: ToSocketAddrs>(addrs: A, secret: SecretId) -> Option<Self> {
         Some(Self {
-            id,
+            id: secret.id(),
+            secret,
             sock: UdpSocket::bind(addrs).ok()?,
+            accepts_neighbors: true,
+            difficulty: 0,
+            stamp: Stamp::default(),
             verified_addrs: HashMap::new(),
             challenges: HashMap::new(),
+            sessions: HashMap::new(),
+            peer_ids: HashMap::new(),
+            peer_addrs: HashMap::new(),
             pending: HashMap::new(),
             recent_broadcasts: HashMap::new(),
         })
@@ -49,10 +83,70 @@ impl Network {
         let _ = self.sock.set_read_timeout(timeout);
     }
 
-    pub fn listen(&self) -> Option<(SocketAddr, Message)> {
-        let mut buf = [0u8; 2000];
+    /// Changes the identity used for future handshakes.
+    ///
+    /// Sessions are bound to the old identity, so they are dropped and addrs verified again.
+    pub fn set_secret(&mut self, secret: SecretId) {
+        self.id = secret.id();
+        self.secret = secret;
+        self.stamp = Stamp::mint(&self.id, self.difficulty);
+        self.verified_addrs.clear();
+        self.sessions.clear();
+        self.peer_ids.clear();
+        self.peer_addrs.clear();
+    }
+
+    /// Sets the proof-of-work required from neighbors, defaults to 0.
+    ///
+    /// Our own stamp is minted to the same difficulty, which may take a while for large values.
+    pub fn set_difficulty(&mut self, difficulty: u32) {
+        self.difficulty = difficulty;
+        self.stamp = Stamp::mint(&self.id, difficulty);
+    }
+
+    /// Sets if we offer to be a neighbor to nodes that verify us, defaults to true
+    pub fn set_accepts_neighbors(&mut self, accepts_neighbors: bool) {
+        self.accepts_neighbors = accepts_neighbors;
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.sock.local_addr().ok()
+    }
+
+    /// Receives the next message.
+    ///
+    /// Only signed, recently sent `Verify` and `Verified` messages are accepted in plaintext,
+    /// everything else must be sealed with a session from a completed handshake, by the
+    /// Id that session was made with.
+    pub fn listen(&mut self) -> Option<(SocketAddr, Message)> {
+        let mut buf = vec![0u8; MAX_PACKET];
         let (byte_count, from_addr) = self.sock.recv_from(&mut buf).ok()?;
-        Message::deserialize(&buf[..byte_count]).map(|msg| (from_addr, msg))
+        let msg = match Packet::decode(&buf[..byte_count])? {
+            Packet::Plain(msg) => {
+                let is_handshake = matches!(
+                    msg.msg_type(),
+                    MessageType::Verify { .. } | MessageType::Verified { .. }
+                );
+                let is_fresh = msg
+                    .sent()
+                    .is_some_and(|sent| now().abs_diff(sent) <= HANDSHAKE_WINDOW.as_millis() as u64);
+                if !is_handshake || !msg.is_signed() || !is_fresh {
+                    return None;
+                }
+                *msg
+            }
+            Packet::Sealed(_) => self
+                .sessions
+                .get_mut(&from_addr)?
+                .iter_mut()
+                .rev()
+                .find_map(|session| {
+                    let msg = session.open(&buf[..byte_count])?;
+                    // a peer may only speak for itself over its session
+                    (msg.from() == session.peer()).then_some(msg)
+                })?,
+        };
+        Some((from_addr, msg))
     }
 
     pub fn send<A: ToSocketAddrs>(&mut self, addrs: A, msg: Message) -> bool {
@@ -71,10 +165,10 @@ impl Network {
     }
     pub fn send_addr<A: Into<SocketAddr>>(&mut self, addr: A, msg: Message) -> bool {
         let addr = addr.into();
-        let sent = send_addr(&self.sock, &mut self.verified_addrs, addr, &msg);
+        let sent = send_addr(&self.sock, &mut self.verified_addrs, &mut self.sessions, addr, &msg);
         if !sent {
-            self.request_verification(self.id, addr);
-            self.add_pending(addr.into(), msg);
+            self.request_verification(addr);
+            self.add_pending(addr, msg);
         }
         sent
     }
@@ -85,10 +179,10 @@ impl Network {
 
     pub fn send_n(&mut self, msg: Message, n: usize) {
         // if same message has been sent recently, do not repeat
-        if let Some(timeout) = self.recent_broadcasts.get(&msg) {
-            if *timeout + REBROADCAST_TIMEOUT < Instant::now() {
-                return;
-            }
+        if let Some(timeout) = self.recent_broadcasts.get(&msg)
+            && *timeout + REBROADCAST_TIMEOUT < Instant::now()
+        {
+            return;
         }
 
         let neighbors: Vec<_> = self
@@ -104,49 +198,126 @@ impl Network {
         let mut rng = rng();
         let recipients: Vec<_> = neighbors
             .choose_multiple(&mut rng, n)
-            .map(|addr| (*addr).clone())
+            .map(|addr| **addr)
             .collect();
         for recipient in recipients {
-            send_addr(&self.sock, &mut self.verified_addrs, recipient, &msg);
+            send_addr(&self.sock, &mut self.verified_addrs, &mut self.sessions, recipient, &msg);
         }
         self.recent_broadcasts.insert(msg, Instant::now());
     }
 
     /// This node would like to send a message to another node, but first it must verify that node as part of the network.
-    pub fn request_verification(&mut self, from: Id, addr: SocketAddr) {
+    pub fn request_verification(&mut self, addr: SocketAddr) {
         let mut rng = rand::rng();
 
         let challenge = Alphabetic.sample_string(&mut rng, 10);
-        self.challenges.insert(challenge.clone(), addr.clone());
-        let data = Message::verify(from, challenge);
-        let _ = self.sock.send_to(&data.serialize(), addr);
+        let handshake = Handshake::new();
+        let data = Message::verify(self.id, challenge.clone(), self.stamp, handshake.key(), addr)
+            .signed(&self.secret);
+        self.challenges
+            .insert(challenge, (addr, handshake, Instant::now()));
+        let _ = self.sock.send_to(&Packet::plain(&data), addr);
     }
 
     pub fn challenge_exists(&self, challenge: &String) -> bool {
         self.challenges.contains_key(challenge)
     }
 
-    /// This node has received a verify challenge and must return it.
-    pub fn verify(&self, addr: &SocketAddr, challenge: String) {
-        let _ = self.sock.send_to(
-            &Message::verified(self.id, challenge, true).serialize(),
-            addr,
-        );
+    /// This node has received a verify challenge from `from` at `addr` and must return it.
+    ///
+    /// `to` is the address the challenge was sent to. Our reply proves that our Id is
+    /// reachable there by signing it along with the challenge and the key exchange.
+    /// Our half of the key exchange is sent along with the challenge,
+    /// and the resulting session is used for messages from that addr.
+    pub fn verify(
+        &mut self,
+        addr: &SocketAddr,
+        from: Id,
+        challenge: String,
+        stamp: &Stamp,
+        key: &HandshakeKey,
+        to: &SocketAddr,
+    ) {
+        let handshake = Handshake::new();
+        let our_key = handshake.key();
+        let proof = self
+            .secret
+            .sign(&proof_bytes(&challenge, to, addr, &our_key, key));
+        let Some(session) = handshake.finish(key, false, &self.id, &from, &challenge) else {
+            return;
+        };
+        self.add_session(*addr, session);
+
+        // only offer to be a neighbor to nodes that have done enough work
+        let can_be_neighbor = self.accepts_neighbors && stamp.meets(&from, self.difficulty);
+        let msg = Message::verified(self.id, challenge, can_be_neighbor, self.stamp, our_key, *addr, proof)
+            .signed(&self.secret);
+        let _ = self.sock.send_to(&Packet::plain(&msg), addr);
     }
 
-    /// Another node as returned our challenge and we can now send the messages to them
-    pub fn verified(&mut self, challenge: &String, is_neighbor: bool) {
-        if let Some(addr) = self.challenges.remove(challenge) {
-            self.verified_addrs
-                .insert(addr.into(), (Instant::now(), is_neighbor));
+    /// Another node as returned our challenge and we can now send the messages to them.
+    ///
+    /// `proof` must be `from`'s signature over the challenge, the address we sent it to,
+    /// the address it `observed` us at and both halves of the key exchange.
+    /// Once it checks out, `from` is recorded as the Id reachable at that address.
+    /// Returns the addr if it has become our neighbor.
+    #[allow(clippy::too_many_arguments)]
+    pub fn verified(
+        &mut self,
+        from: Id,
+        challenge: &String,
+        is_neighbor: bool,
+        stamp: &Stamp,
+        key: &HandshakeKey,
+        observed: &SocketAddr,
+        proof: &Signature,
+    ) -> Option<SocketAddr> {
+        let (addr, handshake, _issued) = self.challenges.remove(challenge)?;
+        let proven = proof_bytes(challenge, &addr, observed, key, &handshake.key());
+        if !from.verify(&proven, proof) {
+            return None;
+        }
+        let session = handshake.finish(key, true, &self.id, &from, challenge)?;
+        self.add_session(addr, session);
+        // only become neighbors with nodes that have done enough work
+        let is_neighbor = is_neighbor && stamp.meets(&from, self.difficulty);
+        self.verified_addrs
+            .insert(addr, (Instant::now(), is_neighbor));
+        if let Some(old_id) = self.peer_ids.insert(addr, from) {
+            self.peer_addrs.remove(&old_id);
+        }
+        self.peer_addrs.insert(from, addr);
 
-            // send pending
-            if let Some(pending) = self.pending.remove(&addr) {
-                for (msg, _sent_time) in pending {
-                    send_addr(&self.sock, &mut self.verified_addrs, addr, &msg);
-                }
+        // send pending
+        if let Some(pending) = self.pending.remove(&addr) {
+            for (msg, _sent_time) in pending {
+                send_addr(&self.sock, &mut self.verified_addrs, &mut self.sessions, addr, &msg);
             }
         }
+        is_neighbor.then_some(addr)
+    }
+
+    /// The Id proven to be reachable at a verified addr
+    pub fn peer_id(&self, addr: &SocketAddr) -> Option<&Id> {
+        if !self.verified_addrs.contains_key(addr) {
+            return None;
+        }
+        self.peer_ids.get(addr)
+    }
+
+    /// The verified addr at which an Id is reachable
+    pub fn addr_of(&self, id: &Id) -> Option<&SocketAddr> {
+        self.peer_addrs
+            .get(id)
+            .filter(|addr| self.verified_addrs.contains_key(*addr))
+    }
+
+    fn add_session(&mut self, addr: SocketAddr, session: Session) {
+        let sessions = self.sessions.entry(addr).or_default();
+        sessions.push(session);
+        if sessions.len() > SESSIONS_PER_ADDR {
+            sessions.remove(0);
+        }
     }
 
     fn add_pending(&mut self, addr: SocketAddr, msg: Message) {
@@ -161,8 +332,21 @@ impl Network {
                 (*verification_time + VERIFICATION_TIMEOUT) >= Instant::now()
             });
 
-        // Clean Challenges (Need to store time challenge was issued)
-        // self.challenges.retain(|x, y|);
+        // forget the Ids of addrs that are no longer verified
+        self.peer_ids
+            .retain(|addr, _id| self.verified_addrs.contains_key(addr));
+        self.peer_addrs
+            .retain(|_id, addr| self.verified_addrs.contains_key(addr));
+
+        // Clean Challenges
+        self.challenges
+            .retain(|_, (_addr, _handshake, issued)| *issued + CHALLENGE_TIMEOUT > Instant::now());
+
+        // Clean sessions
+        self.sessions.retain(|_, sessions| {
+            sessions.retain(|session| session.established() + VERIFICATION_TIMEOUT >= Instant::now());
+            !sessions.is_empty()
+        });
 
         // Clean recent broadcasts
         self.recent_broadcasts
@@ -173,7 +357,7 @@ impl Network {
             if self.verified_addrs.contains_key(addr) {
                 // send messages, remove
                 for (msg, _timeout) in messages {
-                    send_addr(&self.sock, &mut self.verified_addrs, *addr, msg);
+                    send_addr(&self.sock, &mut self.verified_addrs, &mut self.sessions, *addr, msg);
                 }
                 false
             } else {
@@ -217,13 +401,13 @@ impl Network {
                 let mut addrs = new_neighbor.to_socket_addrs().ok()?;
                 addrs
                     .next()
-                    .filter(|addr| !self.verified_addrs.contains_key(&addr))
+                    .filter(|addr| !self.verified_addrs.contains_key(addr))
             })
             .take(connection_deficit)
             .collect::<Vec<_>>();
 
         for addr in addrs {
-            self.request_verification(self.id, addr);
+            self.request_verification(addr);
         }
     }
 }
@@ -231,6 +415,7 @@ impl Network {
 fn send_addr<A: Into<SocketAddr>>(
     sock: &UdpSocket,
     verified_addrs: &mut HashMap<SocketAddr, (Instant, bool)>,
+    sessions: &mut HashMap<SocketAddr, Vec<Session>>,
     addr: A,
     msg: &Message,
 ) -> bool {
@@ -238,31 +423,35 @@ fn send_addr<A: Into<SocketAddr>>(
     // check addr against verified addrs
 
     let entry = verified_addrs.entry(addr);
-    let verified = match &entry {
-        Entry::Occupied(occupied_entry) => {
+    let session = sessions.get_mut(&addr).and_then(|sessions| sessions.last_mut());
+    let verified = match (&entry, &session) {
+        (Entry::Occupied(occupied_entry), Some(_)) => {
             let (verification_time, _is_neighbor) = occupied_entry.get();
-            if (*verification_time + VERIFICATION_TIMEOUT) < Instant::now() {
-                false
-            } else {
-                true
-            }
+            (*verification_time + VERIFICATION_TIMEOUT) >= Instant::now()
         }
         _ => false,
     };
 
     // send the message if verified
-    if verified {
+    if let (true, Some(session)) = (verified, session) {
         // send msg
-        let data = msg.serialize();
-        let _ = sock
-            .send_to(&data, entry.key())
-            .expect("Send msg should succeed");
-    }
-    if let Entry::Occupied(occupied_entry) = entry {
-        if !verified {
-            occupied_entry.remove();
+        let data = session.seal(msg);
+        if let Err(err) = sock.send_to(&data, entry.key()) {
+            eprintln!("failed to send {} bytes to {}: {err}", data.len(), entry.key());
         }
     }
+    if let Entry::Occupied(occupied_entry) = entry
+        && !verified
+    {
+        occupied_entry.remove();
+    }
 
     verified
 }
+
+/// Milliseconds since the unix epoch, the time messages are signed with
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_millis() as u64)
+}
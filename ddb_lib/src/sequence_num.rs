@@ -1,17 +1,90 @@
-use std::cmp::Ordering;
+use std::{
+	cmp::Ordering,
+	fmt::Display,
+	num::ParseIntError,
+	str::FromStr,
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
+/// How far ahead of our clock a sequence number may be before it is ignored by `Clock::observe`
+const MAX_DRIFT: Duration = Duration::from_secs(60);
 
-
-
-#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+/// A hybrid logical clock timestamp.
+///
+/// Ordered by physical time in milliseconds since the unix epoch, then by a counter
+/// that separates events within the same millisecond. Entries with equal sequence
+/// numbers are ordered by their author, see `Entry::order`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize)]
 pub struct SequenceNumber{
-	pub num: u64
+	pub time: u64,
+	pub counter: u32,
 }
 
 impl SequenceNumber {
-	pub const ZERO: Self = Self{num: 0};
+	pub const ZERO: Self = Self{time: 0, counter: 0};
 
 	pub fn order(&self, other: &Self) -> Ordering {
-		Ordering::Equal
+		self.cmp(other)
+	}
+}
+
+/// Written as `time.counter`
+impl Display for SequenceNumber {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}.{}", self.time, self.counter)
+	}
+}
+
+impl FromStr for SequenceNumber {
+	type Err = ParseIntError;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let (time, counter) = s.split_once('.').unwrap_or((s, "0"));
+		Ok(Self{time: time.parse()?, counter: counter.parse()?})
+	}
+}
+
+/// Issues sequence numbers that are later than any this node has issued or seen
+#[derive(Debug, Default)]
+pub struct Clock {
+	last: SequenceNumber,
+}
+
+impl Clock {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// A new sequence number for a local event
+	pub fn tick(&mut self) -> SequenceNumber {
+		let now = now();
+		self.last = if now > self.last.time {
+			SequenceNumber{time: now, counter: 0}
+		} else if let Some(counter) = self.last.counter.checked_add(1) {
+			SequenceNumber{time: self.last.time, counter}
+		} else {
+			// the counter has run out, move on to the next millisecond instead
+			SequenceNumber{time: self.last.time + 1, counter: 0}
+		};
+		self.last
 	}
-}
\ No newline at end of file
+
+	/// Moves the clock past a sequence number from elsewhere.
+	///
+	/// Returns false, leaving the clock unchanged, if it is too far in the future to be trusted.
+	pub fn observe(&mut self, seq: &SequenceNumber) -> bool {
+		if seq.time > now() + MAX_DRIFT.as_millis() as u64 {
+			return false;
+		}
+		if *seq > self.last {
+			self.last = *seq;
+		}
+		true
+	}
+}
+
+/// Milliseconds since the unix epoch
+pub(crate) fn now() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map_or(0, |time| time.as_millis() as u64)
+}
@@ -0,0 +1,46 @@
+use sha2::{Digest, Sha256};
+
+use crate::id::Id;
+
+/// Proof-of-work bound to an Id, carried by `Verify` and `Verified`.
+///
+/// The stamp's work is the number of leading zero bits in
+/// `sha256("ddb stamp" || id || nonce)`. Finding a stamp with `n` bits of work takes
+/// around `2^n` hashes, which makes creating many identities expensive while checking
+/// a stamp only takes one hash.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub struct Stamp {
+    nonce: u64,
+}
+
+impl Stamp {
+    /// Searches for a stamp for `id` with at least `difficulty` bits of work
+    pub fn mint(id: &Id, difficulty: u32) -> Self {
+        (0..=u64::MAX)
+            .map(|nonce| Stamp { nonce })
+            .find(|stamp| stamp.work(id) >= difficulty)
+            .expect("a stamp should be found before the nonces run out")
+    }
+
+    /// Bits of work this stamp represents for `id`
+    pub fn work(&self, id: &Id) -> u32 {
+        let hash = Sha256::new()
+            .chain_update(b"ddb stamp")
+            .chain_update(id.as_bytes())
+            .chain_update(self.nonce.to_le_bytes())
+            .finalize();
+
+        let mut work = 0;
+        for byte in hash {
+            work += byte.leading_zeros();
+            if byte != 0 {
+                break;
+            }
+        }
+        work
+    }
+
+    pub fn meets(&self, id: &Id, difficulty: u32) -> bool {
+        self.work(id) >= difficulty
+    }
+}
@@ -0,0 +1,723 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt::Display,
+    ops::Bound::{self, Excluded, Included, Unbounded},
+    str::FromStr,
+};
+
+use crate::{
+    entry::{Entry, EntryHash},
+    id::Id,
+    message::{REPLY_BUDGET, encoded_len},
+    sequence_num::{SequenceNumber, now},
+    value::{State, Value},
+};
+
+/// How much an author is trusted, higher ranks first
+pub type Trust<'a> = &'a dyn Fn(&Id) -> f32;
+
+/// Where a node keeps its entries.
+///
+/// Entries from revoked authors are kept, but hidden from `get`.
+/// A tombstone hides itself and every entry for its key up to and including its sequence number.
+pub trait Store {
+    fn insert(&mut self, entry: Entry);
+
+    /// Inserts several entries together, a store that persists them does so in one step
+    fn ingest(&mut self, entries: Vec<Entry>) {
+        for entry in entries {
+            self.insert(entry);
+        }
+    }
+
+    /// Does the store already have a copy of this Entry
+    fn contains(&self, entry: &Entry) -> bool;
+
+    /// Whether compaction dropped the tombstone hiding this entry, so it is discarded rather than stored
+    fn is_compacted(&self, entry: &Entry) -> bool;
+
+    /// The newest `count` entries for a key, newest first.
+    ///
+    /// Entries with the same sequence number are ranked by the `trust` of their authors. Concurrent
+    /// writes with different sequence numbers stay in sequence order, `resolve` ranks those by trust.
+    fn get(&self, key: &str, count: usize, trust: Trust) -> Vec<Entry>;
+
+    /// The entries for a key that no other entry supersedes, newest first.
+    ///
+    /// More than one head means the key was written concurrently.
+    fn heads(&self, key: &str) -> Vec<Entry>;
+
+    /// Picks a single value for a key from its heads, returning it and the competing values.
+    ///
+    /// The head from the most trusted author wins, then the newest. Alternatives are ranked the same way.
+    fn resolve(&self, key: &str, trust: Trust) -> (Option<Entry>, Vec<Entry>) {
+        let mut heads = self.heads(key);
+        heads.sort_by(|a, b| trust(&b.id).total_cmp(&trust(&a.id)).then_with(|| b.order(a)));
+        let mut heads = heads.into_iter();
+        (heads.next(), heads.collect())
+    }
+
+    /// Entries written by `author` after the cursor `after`, oldest first.
+    ///
+    /// Includes tombstones and entries they hide, so changes can be followed, but not expired entries.
+    /// Returns at most `limit` entries, and where the next page starts if more remain.
+    fn by_author(&self, author: &Id, after: Option<Cursor>, limit: usize) -> (Vec<Entry>, Option<Cursor>);
+
+    /// Entries for a key after the cursor `after`, oldest first, paginated like `by_author`.
+    ///
+    /// Entries written in a batch come with the rest of their batch, as a batch is only stored whole.
+    fn changes(&self, key: &str, after: Option<Cursor>, limit: usize) -> (Vec<Entry>, Option<Cursor>);
+
+    /// Keys within a range that have a visible entry, in lexicographic order.
+    ///
+    /// A range that ends before it starts has no keys.
+    fn keys<'a>(&'a self, from: Bound<&str>, to: Bound<&str>) -> Box<dyn Iterator<Item = &'a str> + 'a>;
+
+    /// The merged value of a key, see `State::merge`
+    fn state(&self, key: &str, trust: Trust) -> Option<State> {
+        let entries = self.get(key, usize::MAX, trust);
+        let (winner, alternatives) = self.resolve(key, trust);
+        let heads: Vec<_> = winner.into_iter().chain(alternatives).collect();
+        State::merge(&entries, &heads)
+    }
+
+    /// Marks every entry written by `id` as revoked
+    fn revoke(&mut self, id: Id);
+
+    fn is_revoked(&self, id: &Id) -> bool;
+
+    /// Every stored entry, including those from revoked authors
+    fn entries(&self) -> Box<dyn Iterator<Item = &Entry> + '_>;
+
+    /// Ids whose entries are hidden
+    fn revoked(&self) -> Box<dyn Iterator<Item = &Id> + '_>;
+
+    /// Keys whose tombstones compaction dropped, with the sequence number of the newest one
+    fn compacted(&self) -> Box<dyn Iterator<Item = (&str, SequenceNumber)> + '_>;
+
+    /// Drops entries hidden by tombstones, and tombstones older than `expiry` in milliseconds since the unix epoch.
+    ///
+    /// A dropped tombstone's sequence number is remembered for its key, so older entries still
+    /// arriving for the key stay hidden, see `is_compacted`. Returns the number of entries dropped.
+    fn compact(&mut self, expiry: u64) -> usize;
+
+    /// Drops entries that have expired by `now`, returns how many were dropped
+    fn expire(&mut self, now: u64) -> usize;
+
+    /// Called regularly so the store can tidy up after itself
+    fn maintain(&mut self) {}
+}
+
+/// Where a page of `by_author` or `changes` ended, the next page starts after it.
+///
+/// Entries are paged in sequence order, then by author and key, so a page can end between
+/// entries sharing a sequence number. Written as `time.counter`, followed by `/id/key` unless
+/// the page ended after every entry with that sequence number.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub struct Cursor {
+    pub seq: SequenceNumber,
+    /// The author and key of the last entry at `seq` already seen, or None if all of them were
+    pub last: Option<(Id, String)>,
+}
+
+impl Cursor {
+    /// Continues after `entry`
+    pub fn after(entry: &Entry) -> Self {
+        Self {
+            seq: entry.seq,
+            last: Some((entry.id, entry.key.clone())),
+        }
+    }
+}
+
+/// Continues after every entry with the sequence number
+impl From<SequenceNumber> for Cursor {
+    fn from(seq: SequenceNumber) -> Self {
+        Self { seq, last: None }
+    }
+}
+
+impl Display for Cursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.last {
+            Some((id, key)) => write!(f, "{}/{id}/{key}", self.seq),
+            None => write!(f, "{}", self.seq),
+        }
+    }
+}
+
+impl FromStr for Cursor {
+    type Err = ParseCursorError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (seq, last) = match s.split_once('/') {
+            Some((seq, last)) => {
+                let (id, key) = last.split_once('/').ok_or(ParseCursorError)?;
+                (seq, Some((id.parse().map_err(|_| ParseCursorError)?, key.to_string())))
+            }
+            None => (s, None),
+        };
+        let seq = seq.parse().map_err(|_| ParseCursorError)?;
+        Ok(Self { seq, last })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseCursorError;
+
+impl Display for ParseCursorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "cursor should be a sequence number, optionally followed by /id/key")
+    }
+}
+
+impl std::error::Error for ParseCursorError {}
+
+/// Collects entries, grouped with the rest of their batch and given in order with the cursor after
+/// each group, into a page of at most `limit` entries that fits in `REPLY_BUDGET` once encoded.
+///
+/// A batch is never split, so a page holds at least one however large it is.
+/// Returns where the next page starts if entries remain.
+fn page<'a>(groups: impl Iterator<Item = (Cursor, Vec<&'a Entry>)>, limit: usize) -> (Vec<Entry>, Option<Cursor>) {
+    let mut page = Vec::new();
+    let mut size = 0;
+    let mut last = None;
+    for (cursor, group) in groups {
+        let group_size: usize = group.iter().map(|entry| encoded_len(*entry)).sum();
+        if !page.is_empty() && (page.len() + group.len() > limit || size + group_size > REPLY_BUDGET) {
+            return (page, last);
+        }
+        size += group_size;
+        page.extend(group.into_iter().cloned());
+        last = Some(cursor);
+    }
+    (page, None)
+}
+
+/// Whether a range holds nothing, `BTreeMap::range` panics on these rather than returning nothing
+fn is_empty_range(from: Bound<&str>, to: Bound<&str>) -> bool {
+    match (from, to) {
+        (Included(from), Included(to)) => from > to,
+        (Included(from) | Excluded(from), Included(to) | Excluded(to)) => from >= to,
+        _ => false,
+    }
+}
+
+/// A store held only in memory, everything is lost when it is dropped
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    /// Map from keys, in order, to what is held for them
+    incorporated_data: BTreeMap<String, KeyData>,
+    /// The sequence numbers and keys of each author's entries
+    authors: HashMap<Id, BTreeSet<(SequenceNumber, String)>>,
+    revoked: HashSet<Id>,
+    /// Keys holding entries hidden by a tombstone, for the next compaction to drop
+    hidden: BTreeSet<String>,
+    /// The newest tombstone of each key, oldest first, so compaction finds those that expired
+    tombstones: BTreeSet<(SequenceNumber, String)>,
+    /// When each entry with a ttl expires, soonest first, with its key, sequence number and author,
+    /// so sweeping only visits the entries that are due
+    expiring: BTreeSet<(u64, String, SequenceNumber, Id)>,
+}
+
+/// What a store holds for one key
+#[derive(Debug, Default)]
+struct KeyData {
+    /// Entries in sequence order, then by author
+    entries: BTreeMap<SequenceNumber, BTreeMap<Id, Entry>>,
+    /// The sequence number of the newest tombstone, from an author that has not been revoked
+    tombstone: Option<SequenceNumber>,
+    /// The newest tombstone compaction dropped, entries up to it are discarded even if they arrive again
+    compacted: Option<SequenceNumber>,
+    /// The visible entries merged, kept up to date as entries arrive
+    merged: Merged,
+}
+
+/// Heads, counter and set operations merged, so reading a key does not go through its whole history
+#[derive(Debug, Default)]
+struct Merged {
+    /// Positions of the entries that no other entry supersedes, by hash
+    heads: HashMap<EntryHash, (SequenceNumber, Id)>,
+    /// Parents of the merged entries
+    superseded: HashSet<EntryHash>,
+    /// Sum of counter increments
+    count: i128,
+    /// Hashes of the adds of each set element
+    adds: BTreeMap<String, HashSet<EntryHash>>,
+    /// Adds observed by removes
+    removed: HashSet<EntryHash>,
+    /// When the first merged entry expires, after that the merged values are out of date until the entry is swept
+    expires: Option<u64>,
+}
+
+impl Merged {
+    fn add(&mut self, entry: &Entry) {
+        let hash = entry.hash();
+        for parent in &entry.parents {
+            self.superseded.insert(*parent);
+            self.heads.remove(parent);
+        }
+        if !self.superseded.contains(&hash) {
+            self.heads.insert(hash, (entry.seq, entry.id));
+        }
+        match &entry.val {
+            Value::GCounter(amount) => self.count += (*amount).min(i64::MAX as u64) as i128,
+            Value::PnCounter(amount) => self.count += *amount as i128,
+            Value::SetAdd(elem) => {
+                self.adds.entry(elem.clone()).or_default().insert(hash);
+            }
+            Value::SetRemove { observed, .. } => self.removed.extend(observed),
+            _ => {}
+        }
+        if let Some(expires) = entry.expires {
+            self.expires = Some(self.expires.map_or(expires, |earliest| earliest.min(expires)));
+        }
+    }
+
+    /// The value of a key, read as the kind of its newest entry, see `State::merge`
+    fn state(&self, newest: &Entry, heads: &[Entry]) -> Option<State> {
+        let state = match &newest.val {
+            Value::GCounter(_) | Value::PnCounter(_) => {
+                State::Counter(self.count.clamp(i64::MIN as i128, i64::MAX as i128) as i64)
+            }
+            Value::SetAdd(_) | Value::SetRemove { .. } => State::Set(
+                self.adds
+                    .iter()
+                    .filter(|(_elem, adds)| !adds.is_subset(&self.removed))
+                    .map(|(elem, _adds)| elem.clone())
+                    .collect(),
+            ),
+            // registers only depend on the newest entry and the heads
+            _ => return State::merge(std::slice::from_ref(newest), heads),
+        };
+        Some(state)
+    }
+}
+
+impl KeyData {
+    /// Entries up to and including this sequence number are hidden
+    fn deleted(&self) -> Option<SequenceNumber> {
+        self.tombstone.max(self.compacted)
+    }
+
+    /// Whether some entries are hidden, and wait to be dropped by compaction
+    fn has_hidden(&self) -> bool {
+        let first = self.entries.keys().next();
+        self.tombstone
+            .is_some_and(|tombstone| first.is_some_and(|first| *first < tombstone))
+    }
+
+    /// Merges the visible entries again, after some were hidden or taken out
+    fn remerge(&mut self, revoked: &HashSet<Id>) {
+        let start = self.deleted().map_or(Unbounded, Excluded);
+        let mut merged = Merged::default();
+        for entry in self
+            .entries
+            .range((start, Unbounded))
+            .flat_map(|(_seq, seq_value)| seq_value.values())
+            .filter(|entry| !revoked.contains(&entry.id))
+        {
+            merged.add(entry);
+        }
+        self.merged = merged;
+    }
+
+    /// Takes out the entries before `seq`, and those at `seq` too if `inclusive`
+    fn take_before(&mut self, seq: SequenceNumber, inclusive: bool) -> BTreeMap<SequenceNumber, BTreeMap<Id, Entry>> {
+        let kept = self.entries.split_off(&seq);
+        let mut removed = std::mem::replace(&mut self.entries, kept);
+        if inclusive && let Some(seq_value) = self.entries.remove(&seq) {
+            removed.insert(seq, seq_value);
+        }
+        removed
+    }
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Restores a key listed by `Store::compacted`, such as when loading a snapshot
+    pub fn restore_compacted(&mut self, key: String, seq: SequenceNumber) {
+        let key_data = self.incorporated_data.entry(key.clone()).or_default();
+        key_data.compacted = key_data.compacted.max(Some(seq));
+        let removed = key_data.take_before(seq, true);
+        key_data.remerge(&self.revoked);
+        self.forget(&key, removed);
+    }
+
+    /// Whether an entry is read, rather than written by a revoked author or expired
+    fn is_live(&self, entry: &Entry, now: u64) -> bool {
+        !self.is_revoked(&entry.id) && !entry.is_expired(now)
+    }
+
+    /// The entries for a key after its newest tombstone, newest first
+    fn visible(&self, key: &str) -> impl Iterator<Item = &BTreeMap<Id, Entry>> {
+        self.incorporated_data
+            .get(key)
+            .into_iter()
+            .flat_map(|key_data| {
+                let start = key_data.deleted().map_or(Unbounded, Excluded);
+                key_data.entries.range((start, Unbounded)).rev()
+            })
+            .map(|(_seq, seq_value)| seq_value)
+    }
+
+    /// The heads of a key found by going through its live entries, rather than from what was merged
+    fn unmerged_heads(&self, key: &str, now: u64) -> Vec<Entry> {
+        let entries: Vec<_> = self
+            .visible(key)
+            .flat_map(|seq_value| seq_value.values().rev())
+            .filter(|entry| self.is_live(entry, now))
+            .collect();
+        let superseded: HashSet<_> = entries
+            .iter()
+            .flat_map(|entry| entry.parents.iter())
+            .collect();
+        entries
+            .into_iter()
+            .filter(|entry| !superseded.contains(&entry.hash()))
+            .cloned()
+            .collect()
+    }
+
+    /// The entries written in the same batch as `entry`, itself included, in key order
+    fn batch<'a>(&'a self, entry: &'a Entry) -> Vec<&'a Entry> {
+        if entry.batch == 0 {
+            return vec![entry];
+        }
+        self.authors
+            .get(&entry.id)
+            .into_iter()
+            .flat_map(|written| written.range((Included((entry.seq, String::new())), Unbounded)))
+            .take_while(|(seq, _key)| *seq == entry.seq)
+            .filter_map(|(seq, key)| self.incorporated_data.get(key)?.entries.get(seq)?.get(&entry.id))
+            .filter(|sibling| sibling.batch == entry.batch)
+            .collect()
+    }
+
+    /// Finds the newest tombstone of a key and merges its entries again, after entries were taken out or revoked
+    fn refresh(&mut self, key: &str) {
+        let Some(key_data) = self.incorporated_data.get_mut(key) else {
+            return;
+        };
+        let tombstone = key_data
+            .entries
+            .iter()
+            .rev()
+            .flat_map(|(_seq, seq_value)| seq_value.values())
+            .find(|entry| matches!(entry.val, Value::Tombstone) && !self.revoked.contains(&entry.id))
+            .map(|entry| entry.seq);
+        if let Some(old) = std::mem::replace(&mut key_data.tombstone, tombstone) {
+            self.tombstones.remove(&(old, key.to_string()));
+        }
+        if let Some(tombstone) = tombstone {
+            self.tombstones.insert((tombstone, key.to_string()));
+        }
+        key_data.remerge(&self.revoked);
+        if key_data.has_hidden() {
+            self.hidden.insert(key.to_string());
+        }
+    }
+
+    /// Removes dropped entries of a key from the author index, returns how many there were
+    fn forget(&mut self, key: &str, removed: BTreeMap<SequenceNumber, BTreeMap<Id, Entry>>) -> usize {
+        let mut dropped = 0;
+        for (seq, seq_value) in removed {
+            for (id, entry) in seq_value {
+                if let Some(written) = self.authors.get_mut(&id) {
+                    written.remove(&(seq, key.to_string()));
+                }
+                if let Some(expires) = entry.expires {
+                    self.expiring.remove(&(expires, key.to_string(), seq, id));
+                }
+                dropped += 1;
+            }
+        }
+        dropped
+    }
+
+}
+
+impl Store for MemoryStore {
+    fn insert(&mut self, entry: Entry) {
+        if self.is_compacted(&entry) || self.contains(&entry) {
+            return;
+        }
+        // entries are sorted by key, then in the order of `Entry::order`, trust is applied when they are read
+        let key_data = self.incorporated_data.entry(entry.key.clone()).or_default();
+        let revoked = self.revoked.contains(&entry.id);
+        if matches!(entry.val, Value::Tombstone)
+            && !revoked
+            && key_data.tombstone.is_none_or(|tombstone| tombstone < entry.seq)
+        {
+            if let Some(old) = key_data.tombstone.replace(entry.seq) {
+                self.tombstones.remove(&(old, entry.key.clone()));
+            }
+            self.tombstones.insert((entry.seq, entry.key.clone()));
+            // entries newer than the tombstone may have arrived before it, keep them merged
+            key_data.remerge(&self.revoked);
+        } else if !revoked && key_data.deleted().is_none_or(|deleted| deleted < entry.seq) {
+            key_data.merged.add(&entry);
+        }
+        self.authors
+            .entry(entry.id)
+            .or_default()
+            .insert((entry.seq, entry.key.clone()));
+        if let Some(expires) = entry.expires {
+            self.expiring.insert((expires, entry.key.clone(), entry.seq, entry.id));
+        }
+        let key = entry.key.clone();
+        key_data.entries.entry(entry.seq).or_default().insert(entry.id, entry);
+        if key_data.has_hidden() {
+            self.hidden.insert(key);
+        }
+    }
+
+    /// Finds an entry where the key matches, then where the seq_num matches, then where the id matches
+    fn contains(&self, entry: &Entry) -> bool {
+        let Some(key_data) = self.incorporated_data.get(&entry.key) else {
+            return false;
+        };
+
+        let Some(sequence_entries) = key_data.entries.get(&entry.seq) else {
+            return false;
+        };
+
+        sequence_entries.contains_key(&entry.id)
+    }
+
+    fn is_compacted(&self, entry: &Entry) -> bool {
+        self.incorporated_data
+            .get(&entry.key)
+            .and_then(|key_data| key_data.compacted)
+            .is_some_and(|compacted| entry.seq <= compacted)
+    }
+
+    fn state(&self, key: &str, trust: Trust) -> Option<State> {
+        let key_data = self.incorporated_data.get(key)?;
+        let newest = self.get(key, 1, trust);
+        let (winner, alternatives) = self.resolve(key, trust);
+        let heads: Vec<_> = winner.into_iter().chain(alternatives).collect();
+        if key_data.merged.expires.is_some_and(|expires| expires <= now()) {
+            // an entry expired since it was last swept, so merge from scratch without it
+            return State::merge(&self.get(key, usize::MAX, trust), &heads);
+        }
+        key_data.merged.state(newest.first()?, &heads)
+    }
+
+    fn get(&self, key: &str, count: usize, trust: Trust) -> Vec<Entry> {
+        let now = now();
+        self.visible(key)
+            .flat_map(|seq_value| {
+                let mut competing: Vec<_> = seq_value
+                    .values()
+                    .rev()
+                    .filter(|entry| self.is_live(entry, now))
+                    .collect();
+                // stable, so equally trusted authors stay in id order
+                competing.sort_by(|a, b| trust(&b.id).total_cmp(&trust(&a.id)));
+                competing
+            })
+            .take(count)
+            .cloned()
+            .collect()
+    }
+
+    fn heads(&self, key: &str) -> Vec<Entry> {
+        let Some(key_data) = self.incorporated_data.get(key) else {
+            return Vec::new();
+        };
+        let now = now();
+        if key_data.merged.expires.is_some_and(|expires| expires <= now) {
+            // an entry expired since it was last swept, and may have superseded others
+            return self.unmerged_heads(key, now);
+        }
+        let mut heads: Vec<_> = key_data.merged.heads.values().collect();
+        heads.sort_unstable_by(|a, b| b.cmp(a));
+        heads
+            .into_iter()
+            .filter_map(|(seq, id)| key_data.entries.get(seq)?.get(id))
+            .cloned()
+            .collect()
+    }
+
+    fn by_author(&self, author: &Id, after: Option<Cursor>, limit: usize) -> (Vec<Entry>, Option<Cursor>) {
+        if self.is_revoked(author) {
+            return (Vec::new(), None);
+        }
+        let now = now();
+        let start = after
+            .as_ref()
+            .map_or(Unbounded, |after| Included((after.seq, String::new())));
+        let groups = self
+            .authors
+            .get(author)
+            .into_iter()
+            .flat_map(|written| written.range((start.clone(), Unbounded)))
+            // skip what the previous page ended with
+            .filter(|(seq, key)| match &after {
+                Some(Cursor { seq: after, last: Some((_id, last)) }) if seq == after => key > last,
+                Some(Cursor { seq: after, last: None }) => seq != after,
+                _ => true,
+            })
+            .filter_map(|(seq, key)| self.incorporated_data.get(key)?.entries.get(seq)?.get(author))
+            .filter(|entry| !entry.is_expired(now))
+            .map(|entry| (entry, self.batch(entry)))
+            // a batch comes whole with its first entry
+            .filter(|(entry, batch)| batch.first().is_some_and(|first| first.key == entry.key))
+            .map(|(entry, batch)| (Cursor::after(batch.last().unwrap_or(&entry)), batch));
+        page(groups, limit)
+    }
+
+    fn changes(&self, key: &str, after: Option<Cursor>, limit: usize) -> (Vec<Entry>, Option<Cursor>) {
+        let now = now();
+        let start = after.as_ref().map_or(Unbounded, |after| Included(after.seq));
+        let groups = self
+            .incorporated_data
+            .get(key)
+            .into_iter()
+            .flat_map(|key_data| key_data.entries.range((start, Unbounded)))
+            .flat_map(|(_seq, seq_value)| seq_value.values())
+            // skip what the previous page ended with
+            .filter(|entry| match &after {
+                Some(Cursor { seq, last: Some((last, _key)) }) if entry.seq == *seq => entry.id > *last,
+                Some(Cursor { seq, last: None }) => entry.seq != *seq,
+                _ => true,
+            })
+            .filter(|entry| self.is_live(entry, now))
+            .map(|entry| (Cursor::after(entry), self.batch(entry)));
+        page(groups, limit)
+    }
+
+    fn keys<'a>(&'a self, from: Bound<&str>, to: Bound<&str>) -> Box<dyn Iterator<Item = &'a str> + 'a> {
+        if is_empty_range(from, to) {
+            return Box::new(std::iter::empty());
+        }
+        let now = now();
+        Box::new(
+            self.incorporated_data
+                .range::<str, _>((from, to))
+                .map(|(key, _key_data)| key.as_str())
+                .filter(move |key| {
+                    self.visible(key)
+                        .flat_map(|seq_value| seq_value.values())
+                        .any(|entry| self.is_live(entry, now))
+                }),
+        )
+    }
+
+    fn revoke(&mut self, id: Id) {
+        if !self.revoked.insert(id) {
+            return;
+        }
+        // the author's entries, and tombstones, no longer count
+        let keys: BTreeSet<_> = self
+            .authors
+            .get(&id)
+            .into_iter()
+            .flatten()
+            .map(|(_seq, key)| key.clone())
+            .collect();
+        for key in keys {
+            self.refresh(&key);
+        }
+    }
+
+    fn is_revoked(&self, id: &Id) -> bool {
+        self.revoked.contains(id)
+    }
+
+    fn entries(&self) -> Box<dyn Iterator<Item = &Entry> + '_> {
+        Box::new(
+            self.incorporated_data
+                .values()
+                .flat_map(|key_data| key_data.entries.values())
+                .flat_map(|seq_value| seq_value.values()),
+        )
+    }
+
+    fn revoked(&self) -> Box<dyn Iterator<Item = &Id> + '_> {
+        Box::new(self.revoked.iter())
+    }
+
+    fn compacted(&self) -> Box<dyn Iterator<Item = (&str, SequenceNumber)> + '_> {
+        Box::new(
+            self.incorporated_data
+                .iter()
+                .filter_map(|(key, key_data)| Some((key.as_str(), key_data.compacted?))),
+        )
+    }
+
+    fn compact(&mut self, expiry: u64) -> usize {
+        let mut dropped = 0;
+
+        // only keys where something was hidden since the last compaction
+        for key in std::mem::take(&mut self.hidden) {
+            let Some(key_data) = self.incorporated_data.get_mut(&key) else {
+                continue;
+            };
+            let Some(tombstone) = key_data.tombstone else {
+                continue;
+            };
+            let removed = key_data.take_before(tombstone, false);
+            dropped += self.forget(&key, removed);
+        }
+
+        // once expired, a tombstone has done its job and nothing older is still on its way.
+        // The key remembers it, so older entries gossiped again are still discarded
+        while let Some((tombstone, key)) = self.tombstones.pop_first() {
+            if tombstone.time >= expiry {
+                self.tombstones.insert((tombstone, key));
+                break;
+            }
+            let Some(key_data) = self.incorporated_data.get_mut(&key) else {
+                continue;
+            };
+            key_data.tombstone = None;
+            key_data.compacted = key_data.compacted.max(Some(tombstone));
+            let removed = key_data.take_before(tombstone, true);
+            dropped += self.forget(&key, removed);
+        }
+        dropped
+    }
+
+    fn expire(&mut self, now: u64) -> usize {
+        let mut dropped = 0;
+        let mut expired = BTreeSet::new();
+        while let Some((expires, key, seq, id)) = self.expiring.pop_first() {
+            if expires > now {
+                self.expiring.insert((expires, key, seq, id));
+                break;
+            }
+            let Some(key_data) = self.incorporated_data.get_mut(&key) else {
+                continue;
+            };
+            let Some(seq_value) = key_data.entries.get_mut(&seq) else {
+                continue;
+            };
+            if seq_value.remove(&id).is_none() {
+                continue;
+            }
+            if seq_value.is_empty() {
+                key_data.entries.remove(&seq);
+            }
+            if let Some(written) = self.authors.get_mut(&id) {
+                written.remove(&(seq, key.clone()));
+            }
+            dropped += 1;
+            expired.insert(key);
+        }
+        for key in expired {
+            self.refresh(&key);
+            if self
+                .incorporated_data
+                .get(&key)
+                .is_some_and(|key_data| key_data.entries.is_empty() && key_data.compacted.is_none())
+            {
+                self.incorporated_data.remove(&key);
+            }
+        }
+        dropped
+    }
+}
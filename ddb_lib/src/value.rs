@@ -0,0 +1,159 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    fmt::Display,
+};
+
+use crate::{
+    blob::Blob,
+    entry::{Entry, EntryHash},
+};
+
+/// The value written by an entry.
+///
+/// Registers replace earlier values, the other kinds are operations merged with every
+/// other operation on the key, so concurrent writers do not clobber each other.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum Value {
+    /// Last writer wins register
+    Lww(Blob),
+    /// Multi-value register, concurrent writes are all kept until one supersedes them
+    Mv(Blob),
+    /// Increment of a grow only counter
+    GCounter(u64),
+    /// Increment or decrement of a counter
+    PnCounter(i64),
+    /// Adds an element to an observed-remove set
+    SetAdd(String),
+    /// Removes an element from an observed-remove set.
+    ///
+    /// Only the adds in `observed` are removed, so a concurrent add of the same element survives.
+    SetRemove {
+        elem: String,
+        observed: Vec<EntryHash>,
+    },
+    /// Deletes the key, hiding every earlier entry for it
+    Tombstone,
+}
+
+impl From<String> for Value {
+    fn from(val: String) -> Self {
+        Value::Lww(val.into())
+    }
+}
+
+impl From<&str> for Value {
+    fn from(val: &str) -> Self {
+        Value::Lww(val.into())
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Lww(val) | Value::Mv(val) => write!(f, "{val}"),
+            Value::GCounter(amount) => write!(f, "+{amount}"),
+            Value::PnCounter(amount) => write!(f, "{amount:+}"),
+            Value::SetAdd(elem) => write!(f, "add {elem}"),
+            Value::SetRemove { elem, .. } => write!(f, "remove {elem}"),
+            Value::Tombstone => write!(f, "deleted"),
+        }
+    }
+}
+
+/// The merged value of a key
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum State {
+    /// The value of the winning head, picked like `Store::resolve` when writes were concurrent
+    Register(Blob),
+    /// Values of concurrent writes to a multi-value register, ranked like `Store::resolve`
+    Values(Vec<Blob>),
+    Counter(i64),
+    Set(BTreeSet<String>),
+}
+
+impl State {
+    /// Merges the entries of a key, given newest first, and its heads.
+    ///
+    /// The kind of the newest entry decides how the key is read, entries of other kinds are ignored.
+    pub fn merge(entries: &[Entry], heads: &[Entry]) -> Option<State> {
+        let newest = entries.first()?;
+        let state = match &newest.val {
+            Value::Lww(val) => State::Register(
+                heads
+                    .iter()
+                    .find_map(|entry| match &entry.val {
+                        Value::Lww(val) => Some(val.clone()),
+                        _ => None,
+                    })
+                    .unwrap_or_else(|| val.clone()),
+            ),
+            Value::Mv(_) => State::Values(
+                heads
+                    .iter()
+                    .filter_map(|entry| match &entry.val {
+                        Value::Mv(val) => Some(val.clone()),
+                        _ => None,
+                    })
+                    .collect(),
+            ),
+            Value::GCounter(_) | Value::PnCounter(_) => State::Counter(
+                entries
+                    .iter()
+                    .map(|entry| match entry.val {
+                        Value::GCounter(amount) => amount.min(i64::MAX as u64) as i64,
+                        Value::PnCounter(amount) => amount,
+                        _ => 0,
+                    })
+                    .fold(0i64, i64::saturating_add),
+            ),
+            Value::SetAdd(_) | Value::SetRemove { .. } => {
+                let removed: HashSet<_> = entries
+                    .iter()
+                    .flat_map(|entry| match &entry.val {
+                        Value::SetRemove { observed, .. } => observed.as_slice(),
+                        _ => &[],
+                    })
+                    .collect();
+                State::Set(
+                    entries
+                        .iter()
+                        .filter(|entry| !removed.contains(&entry.hash()))
+                        .filter_map(|entry| match &entry.val {
+                            Value::SetAdd(elem) => Some(elem.clone()),
+                            _ => None,
+                        })
+                        .collect(),
+                )
+            }
+            // stores hide tombstones, but a deleted key has no value either way
+            Value::Tombstone => return None,
+        };
+        Some(state)
+    }
+
+    /// The adds of `elem` in a set key's entries, which a remove of `elem` should name as observed
+    pub fn observed_adds(entries: &[Entry], elem: &str) -> Vec<EntryHash> {
+        entries
+            .iter()
+            .filter(|entry| matches!(&entry.val, Value::SetAdd(added) if added == elem))
+            .map(Entry::hash)
+            .collect()
+    }
+}
+
+impl Display for State {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            State::Register(val) => write!(f, "{val}"),
+            State::Values(vals) => {
+                let vals: Vec<_> = vals.iter().map(Blob::to_string).collect();
+                write!(f, "{}", vals.join(" | "))
+            }
+            State::Counter(count) => write!(f, "{count}"),
+            State::Set(elems) => {
+                let elems: Vec<_> = elems.iter().map(String::as_str).collect();
+                write!(f, "{{{}}}", elems.join(", "))
+            }
+        }
+    }
+}
@@ -0,0 +1,30 @@
+#[cfg(test)]
+mod tests {
+    use ddb_lib::{Blob, Message, SecretId, Value};
+
+    #[test]
+    fn binary_values_survive_the_wire() {
+        let secret = SecretId::generate();
+        let data: Vec<u8> = (0..=255).cycle().take(10_000).collect();
+        let blob = Blob::new(Some("application/octet-stream".into()), data);
+        let msg = Message::set(secret.id(), "key".into(), Value::Lww(blob.clone())).signed(&secret);
+
+        let packet = msg.serialize();
+        // raw bytes on the wire, not base64 or a list of numbers
+        assert!(packet.len() < 10_500);
+        let decoded = Message::deserialize(&packet).unwrap();
+        assert!(decoded.is_signed());
+        assert_eq!(decoded, msg);
+
+        let json = serde_json::to_string(&blob).unwrap();
+        assert_eq!(serde_json::from_str::<Blob>(&json).unwrap(), blob);
+    }
+
+    #[test]
+    fn only_text_is_displayed_as_is() {
+        assert_eq!(Blob::from("  spaced  out ").to_string(), "  spaced  out ");
+        let png = Blob::new(Some("image/png".into()), vec![0x89, b'P', b'N', b'G']);
+        assert_eq!(png.to_string(), "<image/png, 4 bytes> iVBORw==");
+        assert_eq!(Blob::from(vec![0xff, 0xfe]).as_text(), None);
+    }
+}
@@ -0,0 +1,48 @@
+#[cfg(test)]
+mod tests {
+    use ddb_lib::{Capability, Entry, SecretId, SequenceNumber};
+
+    #[test]
+    fn delegated_writes_are_accepted() {
+        let (owner, team, node) = (SecretId::generate(), SecretId::generate(), SecretId::generate());
+        let seq = SequenceNumber { time: 1000, counter: 0 };
+        let key = format!("{}/config/port", owner.id());
+
+        let to_team = Capability::new(&owner, team.id(), format!("{}/config/", owner.id()), 2000, true);
+        let to_node = Capability::new(&team, node.id(), format!("{}/config/port", owner.id()), 2000, false);
+        let entry = Entry::new(&node, seq, key.clone(), "8080".into());
+        assert!(!entry.may_write(1000));
+        assert!(entry.clone().with_capabilities(vec![to_team.clone(), to_node.clone()]).may_write(1000));
+
+        // once the grant has expired, writes are refused
+        let late = Entry::new(&node, SequenceNumber { time: 3000, counter: 0 }, key.clone(), "8080".into());
+        assert!(!late.with_capabilities(vec![to_team.clone(), to_node.clone()]).may_write(1000));
+        // even when the entry is backdated to before the grant expired
+        assert!(!entry.clone().with_capabilities(vec![to_team.clone(), to_node.clone()]).may_write(3000));
+
+        // the chain must start with the owner
+        assert!(!entry.clone().with_capabilities(vec![to_node.clone()]).may_write(1000));
+
+        // a grant can only be passed on if it allows it
+        let to_team = Capability::new(&owner, team.id(), format!("{}/config/", owner.id()), 2000, false);
+        assert!(!entry.with_capabilities(vec![to_team, to_node]).may_write(1000));
+    }
+
+    #[test]
+    fn delegation_cannot_widen_or_be_altered() {
+        let (owner, team, node) = (SecretId::generate(), SecretId::generate(), SecretId::generate());
+        let seq = SequenceNumber { time: 1000, counter: 0 };
+        let key = format!("{}/secrets", owner.id());
+
+        let to_team = Capability::new(&owner, team.id(), format!("{}/config/", owner.id()), 2000, true);
+        let to_node = Capability::new(&team, node.id(), format!("{}/", owner.id()), 2000, false);
+        let entry = Entry::new(&node, seq, key, "val".into());
+        assert!(!entry.clone().with_capabilities(vec![to_team, to_node]).may_write(1000));
+
+        let mut to_node = Capability::new(&owner, node.id(), format!("{}/", owner.id()), 2000, false);
+        assert!(to_node.verify());
+        to_node.expiry = u64::MAX;
+        assert!(!to_node.verify());
+        assert!(!entry.with_capabilities(vec![to_node]).may_write(1000));
+    }
+}
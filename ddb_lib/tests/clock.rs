@@ -0,0 +1,38 @@
+#[cfg(test)]
+mod tests {
+    use ddb_lib::{Clock, SequenceNumber};
+
+    #[test]
+    fn ticks_always_advance() {
+        let mut clock = Clock::new();
+        let mut last = clock.tick();
+        for _ in 0..1000 {
+            let next = clock.tick();
+            assert!(next > last);
+            last = next;
+        }
+    }
+
+    #[test]
+    fn observed_numbers_are_overtaken() {
+        let mut clock = Clock::new();
+        let ahead = clock.tick();
+        let ahead = SequenceNumber { time: ahead.time + 10_000, counter: 7 };
+        assert!(clock.observe(&ahead));
+        assert!(clock.tick() > ahead);
+
+        // too far in the future to be believed
+        let far = SequenceNumber { time: u64::MAX, counter: 0 };
+        assert!(!clock.observe(&far));
+        assert!(clock.tick() < far);
+    }
+
+    #[test]
+    fn exhausted_counters_roll_over_to_the_next_millisecond() {
+        let mut clock = Clock::new();
+        let now = clock.tick();
+        let last = SequenceNumber { time: now.time + 1_000, counter: u32::MAX };
+        assert!(clock.observe(&last));
+        assert_eq!(clock.tick(), SequenceNumber { time: last.time + 1, counter: 0 });
+    }
+}
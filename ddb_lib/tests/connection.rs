@@ -1,32 +1,43 @@
 #[cfg(test)]
 mod tests {
-    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+    use std::net::Ipv4Addr;
 
-    use ddb_lib::{Id, Message, MessageType, Network};
+    use ddb_lib::{Message, MessageType, Network, SecretId, Stamp};
 
     #[test]
     fn localhost_send_recv() {
-        // setup listen/send
-        let listen_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 1930);
-		let listen_id = Id::generate();
-        let listener = Network::new(listen_addr, listen_id).unwrap();
+        // setup listen/send, on whichever ports are free
+		let listen_secret = SecretId::generate();
+        let listen_id = listen_secret.id();
+        let mut listener = Network::new((Ipv4Addr::LOCALHOST, 0), listen_secret).unwrap();
+        let listen_addr = listener.local_addr().unwrap();
 
-        let send_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 1931);
-		let send_id = Id::generate();
-        let mut sender = Network::new(send_addr, send_id).unwrap();
+		let send_secret = SecretId::generate();
+        let send_id = send_secret.id();
+        let mut sender = Network::new((Ipv4Addr::LOCALHOST, 0), send_secret).unwrap();
 
         // verify listener to sender
-        sender.request_verification(send_id.clone(), listen_addr);
-        let (_v_addr, v_msg) = listener.listen().expect("verification should be received");
+        sender.request_verification(listen_addr);
+        let (v_addr, v_msg) = listener.listen().expect("verification should be received");
 
-        let MessageType::Verify(challenge, _padding) = v_msg.msg_type() else {
+        let MessageType::Verify { challenge, stamp, key, addr } = v_msg.msg_type() else {
             panic!("Incorrect message type received")
         };
+        listener.verify(&v_addr, *v_msg.from(), challenge.clone(), stamp, key, addr);
 
-        sender.verified(challenge, true);
+        let (_addr, r_msg) = sender.listen().expect("verification reply should be received");
+        let MessageType::Verified { challenge, is_neighbor, stamp, key, observed, proof } = r_msg.msg_type() else {
+            panic!("Incorrect message type received")
+        };
+        let neighbor = sender.verified(*r_msg.from(), challenge, *is_neighbor, stamp, key, observed, proof);
+        assert_eq!(neighbor, Some(listen_addr));
 
+        // the listener has proven its id is reachable at its address
+        assert_eq!(sender.peer_id(&listen_addr), Some(&listen_id));
+        assert_eq!(sender.addr_of(&listen_id), Some(&listen_addr));
 
-		// send test message
+
+		// send test message, it is encrypted with the session from the handshake
         let msg = Message::get(send_id, "test".into(), 4);
         sender.send(listen_addr, msg.clone());
 
@@ -34,4 +45,87 @@ mod tests {
 
         assert_eq!(return_msg, msg);
     }
+
+    #[test]
+    fn unsealed_messages_are_dropped() {
+        let mut listener = Network::new((Ipv4Addr::LOCALHOST, 0), SecretId::generate()).unwrap();
+        listener.set_read_timeout(Some(std::time::Duration::from_millis(200)));
+        let listen_addr = listener.local_addr().unwrap();
+
+        let sock = std::net::UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let secret = SecretId::generate();
+        let msg = Message::get(secret.id(), "test".into(), 4).signed(&secret);
+        let mut packet = vec![0];
+        packet.extend_from_slice(&msg.serialize());
+        sock.send_to(&packet, listen_addr).unwrap();
+
+        assert!(listener.listen().is_none());
+    }
+
+    #[test]
+    fn messages_must_come_from_the_sessions_peer() {
+        let mut listener = Network::new((Ipv4Addr::LOCALHOST, 0), SecretId::generate()).unwrap();
+        listener.set_read_timeout(Some(std::time::Duration::from_millis(200)));
+        let listen_addr = listener.local_addr().unwrap();
+        let mut sender = Network::new((Ipv4Addr::LOCALHOST, 0), SecretId::generate()).unwrap();
+
+        sender.request_verification(listen_addr);
+        let (v_addr, v_msg) = listener.listen().unwrap();
+        let MessageType::Verify { challenge, stamp, key, addr } = v_msg.msg_type() else {
+            panic!("Incorrect message type received")
+        };
+        listener.verify(&v_addr, *v_msg.from(), challenge.clone(), stamp, key, addr);
+        let (_addr, r_msg) = sender.listen().unwrap();
+        let MessageType::Verified { challenge, is_neighbor, stamp, key, observed, proof } = r_msg.msg_type() else {
+            panic!("Incorrect message type received")
+        };
+        sender.verified(*r_msg.from(), challenge, *is_neighbor, stamp, key, observed, proof);
+
+        // claiming to be someone else over our session
+        let other = SecretId::generate();
+        assert!(sender.send(listen_addr, Message::get(other.id(), "test".into(), 4).signed(&other)));
+        assert!(listener.listen().is_none());
+    }
+
+    #[test]
+    fn stamps_below_the_difficulty_are_not_neighbors() {
+        let mut listener = Network::new((Ipv4Addr::LOCALHOST, 0), SecretId::generate()).unwrap();
+        listener.set_difficulty(8);
+        let listen_addr = listener.local_addr().unwrap();
+
+        // the sender's stamp has no work, so the listener does not offer to be its neighbor.
+        // Its unminted stamp may meet the difficulty by chance, so the id is picked to not
+        let secret = std::iter::repeat_with(SecretId::generate)
+            .find(|secret| !Stamp::default().meets(&secret.id(), 8))
+            .unwrap();
+        let mut sender = Network::new((Ipv4Addr::LOCALHOST, 0), secret).unwrap();
+        sender.request_verification(listen_addr);
+        let (v_addr, v_msg) = listener.listen().unwrap();
+        let MessageType::Verify { challenge, stamp, key, addr } = v_msg.msg_type() else {
+            panic!("Incorrect message type received")
+        };
+        listener.verify(&v_addr, *v_msg.from(), challenge.clone(), stamp, key, addr);
+        let (_addr, r_msg) = sender.listen().unwrap();
+        let MessageType::Verified { challenge, is_neighbor, stamp, key, observed, proof } = r_msg.msg_type() else {
+            panic!("Incorrect message type received")
+        };
+        assert!(!is_neighbor);
+        assert_eq!(sender.verified(*r_msg.from(), challenge, *is_neighbor, stamp, key, observed, proof), None);
+        // it is still verified, only not a neighbor
+        assert!(sender.peer_id(&listen_addr).is_some());
+
+        // nor does the listener take one without work as its neighbor when it asks
+        listener.request_verification(sender.local_addr().unwrap());
+        let (v_addr, v_msg) = sender.listen().unwrap();
+        let MessageType::Verify { challenge, stamp, key, addr } = v_msg.msg_type() else {
+            panic!("Incorrect message type received")
+        };
+        sender.verify(&v_addr, *v_msg.from(), challenge.clone(), stamp, key, addr);
+        let (_addr, r_msg) = listener.listen().unwrap();
+        let MessageType::Verified { challenge, is_neighbor, stamp, key, observed, proof } = r_msg.msg_type() else {
+            panic!("Incorrect message type received")
+        };
+        assert!(is_neighbor);
+        assert_eq!(listener.verified(*r_msg.from(), challenge, *is_neighbor, stamp, key, observed, proof), None);
+    }
 }
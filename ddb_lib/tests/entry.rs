@@ -0,0 +1,87 @@
+#[cfg(test)]
+mod tests {
+    use ddb_lib::{Entry, MAX_ENTRY, Message, SecretId, SequenceNumber, Value, key_owner};
+
+    #[test]
+    fn signed_entry_verifies() {
+        let secret = SecretId::generate();
+        let entry = Entry::new(&secret, SequenceNumber::ZERO, "key".into(), "val".into());
+        assert!(entry.verify());
+
+        let json = serde_json::to_vec(&entry).unwrap();
+        let decoded: Entry = serde_json::from_slice(&json).unwrap();
+        assert!(decoded.verify());
+    }
+
+    #[test]
+    fn entries_must_fit_in_a_message() {
+        let secret = SecretId::generate();
+        let entry = Entry::new(&secret, SequenceNumber::ZERO, "key".into(), Value::Lww(vec![0; MAX_ENTRY / 2].into()));
+        assert!(entry.fits());
+        let msg = Message::values(secret.id(), vec![entry.clone()]);
+        assert_eq!(Message::deserialize(&msg.serialize()).unwrap(), msg);
+
+        let large = Entry::new(&secret, SequenceNumber::ZERO, "key".into(), Value::Lww(vec![0; MAX_ENTRY].into()));
+        assert!(!large.fits());
+    }
+
+    #[test]
+    fn altered_entry_fails_verification() {
+        let secret = SecretId::generate();
+        let entry = Entry::new(&secret, SequenceNumber::ZERO, "key".into(), "val".into());
+
+        let mut altered = entry.clone();
+        altered.val = "other".into();
+        assert!(!altered.verify());
+
+        let mut forged = entry.clone();
+        forged.id = SecretId::generate().id();
+        assert!(!forged.verify());
+
+        forged.sign(&secret);
+        assert_eq!(forged.id, secret.id());
+        assert!(forged.verify());
+    }
+
+    #[test]
+    fn only_the_owner_writes_to_a_namespace() {
+        let (owner, other) = (SecretId::generate(), SecretId::generate());
+        let key = format!("{}/config", owner.id());
+        assert_eq!(key_owner(&key), Some(owner.id()));
+        assert_eq!(key_owner("shared/config"), None);
+
+        assert!(Entry::new(&owner, SequenceNumber::ZERO, key.clone(), "val".into()).may_write(0));
+        assert!(!Entry::new(&other, SequenceNumber::ZERO, key, "val".into()).may_write(0));
+        assert!(Entry::new(&other, SequenceNumber::ZERO, "shared/config".into(), "val".into()).may_write(0));
+    }
+
+    #[test]
+    fn batches_are_kept_whole() {
+        let secret = SecretId::generate();
+        let seq = SequenceNumber { time: 5, counter: 0 };
+        let batch: Vec<_> = ["a", "b"]
+            .into_iter()
+            .map(|key| {
+                let mut entry = Entry::new(&secret, seq, key.into(), "val".into());
+                entry.batch = 2;
+                entry.sign(&secret);
+                entry
+            })
+            .collect();
+        assert!(batch.iter().all(Entry::verify));
+        let alone = Entry::new(&secret, SequenceNumber::ZERO, "c".into(), "val".into());
+
+        let mut entries = vec![batch[0].clone(), batch[1].clone(), alone.clone()];
+        Entry::retain_complete_batches(&mut entries);
+        assert_eq!(entries.len(), 3);
+
+        let mut entries = vec![batch[0].clone(), alone.clone()];
+        Entry::retain_complete_batches(&mut entries);
+        assert_eq!(entries, vec![alone]);
+
+        // the batch size is signed, so it cannot be changed to pass off part of a batch
+        let mut altered = batch[0].clone();
+        altered.batch = 1;
+        assert!(!altered.verify());
+    }
+}
@@ -0,0 +1,54 @@
+#[cfg(test)]
+mod tests {
+    use ddb_lib::{Handshake, Message, SecretId, Session};
+
+    /// Both ends of a session between two new nodes
+    fn sessions() -> (Session, Session) {
+        let (initiator, responder) = (SecretId::generate().id(), SecretId::generate().id());
+        let (ours, theirs) = (Handshake::new(), Handshake::new());
+        let (our_key, their_key) = (ours.key(), theirs.key());
+        let ours = ours.finish(&their_key, true, &initiator, &responder, "challenge").unwrap();
+        let theirs = theirs.finish(&our_key, false, &responder, &initiator, "challenge").unwrap();
+        (ours, theirs)
+    }
+
+    #[test]
+    fn sealed_packets_are_opened_once() {
+        let (mut ours, mut theirs) = sessions();
+        let msg = Message::get(SecretId::generate().id(), "key".into(), 1);
+        let first = ours.seal(&msg);
+        let second = ours.seal(&msg);
+        assert_ne!(first, second);
+
+        // out of order is fine, twice is not
+        assert_eq!(theirs.open(&second), Some(msg.clone()));
+        assert_eq!(theirs.open(&first), Some(msg.clone()));
+        assert_eq!(theirs.open(&first), None);
+        assert_eq!(theirs.open(&second), None);
+
+        // both directions count on their own
+        let reply = theirs.seal(&msg);
+        assert_eq!(ours.open(&reply), Some(msg));
+    }
+
+    #[test]
+    fn packets_are_not_opened_by_their_sender() {
+        let (mut ours, mut theirs) = sessions();
+        let msg = Message::get(SecretId::generate().id(), "key".into(), 1);
+        let packet = ours.seal(&msg);
+        assert_eq!(ours.open(&packet), None);
+        assert_eq!(theirs.open(&packet), Some(msg));
+    }
+
+    #[test]
+    fn packets_older_than_the_window_are_dropped() {
+        let (mut ours, mut theirs) = sessions();
+        let msg = Message::get(SecretId::generate().id(), "key".into(), 1);
+        let old = ours.seal(&msg);
+        for _ in 0..100 {
+            let packet = ours.seal(&msg);
+            assert!(theirs.open(&packet).is_some());
+        }
+        assert_eq!(theirs.open(&old), None);
+    }
+}
@@ -0,0 +1,41 @@
+#[cfg(test)]
+mod tests {
+    use ddb_lib::{Id, SecretId, encoded_len};
+
+    #[test]
+    fn id_text_round_trip() {
+        let id = SecretId::generate().id();
+        let parsed: Id = id.to_string().parse().expect("displayed id should parse");
+        assert_eq!(parsed, id);
+
+        let json = serde_json::to_string(&id).unwrap();
+        assert_eq!(serde_json::from_str::<Id>(&json).unwrap(), id);
+
+        assert_eq!(Id::default().to_string().parse::<Id>(), Ok(Id::default()));
+        assert!("1234".parse::<Id>().is_err());
+    }
+
+    #[test]
+    fn ids_and_signatures_are_raw_bytes_on_the_wire() {
+        let secret = SecretId::generate();
+        let (id, sig) = (secret.id(), secret.sign(b"data"));
+        // a two byte header, then the bytes themselves
+        assert_eq!(encoded_len(&id), 2 + 32);
+        assert_eq!(encoded_len(&sig), 2 + 64);
+
+        let data = rmp_serde::to_vec(&(id, sig)).unwrap();
+        assert_eq!(rmp_serde::from_slice::<(Id, _)>(&data).unwrap(), (id, sig));
+    }
+
+    #[test]
+    fn signatures_verify_only_for_signer() {
+        let secret = SecretId::generate();
+        let other = SecretId::generate();
+        let sig = secret.sign(b"data");
+
+        assert!(secret.id().verify(b"data", &sig));
+        assert!(!secret.id().verify(b"other data", &sig));
+        assert!(!other.id().verify(b"data", &sig));
+        assert!(!Id::default().verify(b"data", &sig));
+    }
+}
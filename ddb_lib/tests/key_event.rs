@@ -0,0 +1,67 @@
+#[cfg(test)]
+mod tests {
+    use ddb_lib::{Id, KeyChange, KeyEvent, SecretId};
+
+    #[test]
+    fn key_events_are_signed_by_the_retired_key() {
+        let old = SecretId::generate();
+        let new = SecretId::generate();
+
+        let rotation = KeyEvent::rotate(&old, &new);
+        assert!(rotation.verify());
+        assert_eq!(rotation.id, old.id());
+
+        let revocation = KeyEvent::revoke(&old);
+        assert!(revocation.verify());
+    }
+
+    #[test]
+    fn altered_key_events_fail_verification() {
+        let old = SecretId::generate();
+        let mut rotation = KeyEvent::rotate(&old, &SecretId::generate());
+        let KeyChange::Rotate { accepted, .. } = rotation.change.clone() else {
+            panic!("should be a rotation");
+        };
+        rotation.change = KeyChange::Rotate {
+            successor: SecretId::generate().id(),
+            accepted,
+        };
+        assert!(!rotation.verify());
+
+        // a revocation cannot be announced for someone else's key
+        let mut revocation = KeyEvent::revoke(&old);
+        revocation.id = SecretId::generate().id();
+        assert!(!revocation.verify());
+    }
+
+    /// A key event signed by `secret` whatever its change, as a node making one up would
+    fn announce(secret: &SecretId, change: KeyChange) -> KeyEvent {
+        #[derive(serde::Serialize)]
+        struct SignedFields<'a> {
+            id: &'a Id,
+            change: &'a KeyChange,
+        }
+        let id = secret.id();
+        let sig = secret.sign(&serde_json::to_vec(&SignedFields { id: &id, change: &change }).unwrap());
+        KeyEvent { id, change, sig }
+    }
+
+    #[test]
+    fn rotations_need_the_successor_to_accept() {
+        let (old, new, other) = (SecretId::generate(), SecretId::generate(), SecretId::generate());
+        let accepted_by = |event: KeyEvent| match event.change {
+            KeyChange::Rotate { accepted, .. } => accepted,
+            KeyChange::Revoke => panic!("should be a rotation"),
+        };
+        let accepted = accepted_by(KeyEvent::rotate(&old, &new));
+        assert!(announce(&old, KeyChange::Rotate { successor: new.id(), accepted }).verify());
+
+        // handing the key to someone who did not sign for it
+        let accepted = accepted_by(KeyEvent::rotate(&old, &other));
+        assert!(!announce(&old, KeyChange::Rotate { successor: new.id(), accepted }).verify());
+
+        // an acceptance only counts for the key it was given by
+        let accepted = accepted_by(KeyEvent::rotate(&other, &new));
+        assert!(!announce(&old, KeyChange::Rotate { successor: new.id(), accepted }).verify());
+    }
+}
@@ -0,0 +1,46 @@
+#[cfg(test)]
+mod tests {
+    use ddb_lib::{Entry, Message, MessageType, SecretId, SequenceNumber};
+
+    #[test]
+    fn signed_message_round_trip() {
+        let secret = SecretId::generate();
+        let msg = Message::set(secret.id(), "key".into(), "val".into()).signed(&secret);
+        assert!(msg.is_signed());
+
+        let decoded = Message::deserialize(&msg.serialize()).unwrap();
+        assert!(decoded.is_signed());
+        assert_eq!(decoded.from(), &secret.id());
+    }
+
+    #[test]
+    fn unsigned_or_forged_messages_are_not_signed() {
+        let secret = SecretId::generate();
+        let unsigned = Message::set(secret.id(), "key".into(), "val".into());
+        assert!(!unsigned.is_signed());
+
+        // reuse a valid signature on a different message
+        let signed = Message::set(secret.id(), "key".into(), "val".into()).signed(&secret);
+        let mut json = serde_json::to_value(&signed).unwrap();
+        json["msg_type"]["Set"]["val"]["Lww"]["data"] = "b3RoZXI=".into();
+        let forged: Message = serde_json::from_value(json).unwrap();
+        assert!(!forged.is_signed());
+    }
+
+    #[test]
+    fn compare_and_set_round_trip() {
+        let secret = SecretId::generate();
+        let head = Entry::new(&secret, SequenceNumber::ZERO, "key".into(), "old".into());
+        let msg = Message::compare_and_set(secret.id(), "key".into(), Some(head.hash()), "new".into()).signed(&secret);
+
+        let decoded = Message::deserialize(&msg.serialize()).unwrap();
+        assert!(decoded.is_signed());
+        match decoded.take_msg_type() {
+            MessageType::CompareAndSet { key, expected, .. } => {
+                assert_eq!(key, "key");
+                assert_eq!(expected, Some(head.hash()));
+            }
+            other => panic!("unexpected message {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,22 @@
+#[cfg(test)]
+mod tests {
+    use ddb_lib::{SecretId, Stamp};
+
+    #[test]
+    fn minted_stamp_meets_difficulty() {
+        let id = SecretId::generate().id();
+        let stamp = Stamp::mint(&id, 12);
+        assert!(stamp.work(&id) >= 12);
+        assert!(stamp.meets(&id, 12));
+        assert!(Stamp::default().meets(&id, 0));
+    }
+
+    #[test]
+    fn stamp_is_bound_to_its_id() {
+        let id = SecretId::generate().id();
+        let other = SecretId::generate().id();
+        let stamp = Stamp::mint(&id, 16);
+        // another id meeting the difficulty by chance is a one in 65536 event
+        assert!(!stamp.meets(&other, 16));
+    }
+}
@@ -0,0 +1,408 @@
+#[cfg(test)]
+mod tests {
+    use std::ops::Bound::{Excluded, Included, Unbounded};
+
+    use ddb_lib::{
+        Clock, Cursor, Entry, Id, MemoryStore, Message, MessageType, SecretId, SequenceNumber,
+        State, Store, Value,
+    };
+
+    #[test]
+    fn memory_store_returns_newest_first() {
+        let secret = SecretId::generate();
+        let mut clock = Clock::new();
+        let mut store = MemoryStore::new();
+
+        let first = Entry::new(&secret, clock.tick(), "key".into(), "a".into());
+        store.insert(first.clone());
+        let second = Entry::new(&secret, clock.tick(), "key".into(), "b".into());
+        store.insert(second.clone());
+
+        assert!(store.contains(&first));
+        assert_eq!(store.get("key", 1, &|_| 0.5), vec![second.clone()]);
+        assert_eq!(store.get("key", 5, &|_| 0.5), vec![second, first]);
+        assert_eq!(store.entries().count(), 2);
+    }
+
+    #[test]
+    fn concurrent_writes_are_ordered_by_author() {
+        let seq = SequenceNumber { time: 5, counter: 0 };
+        let mut a = Entry::new(&SecretId::generate(), seq, "key".into(), "a".into());
+        let mut b = Entry::new(&SecretId::generate(), seq, "key".into(), "b".into());
+        if a.order(&b).is_gt() {
+            std::mem::swap(&mut a, &mut b);
+        }
+
+        let mut store = MemoryStore::new();
+        store.insert(a.clone());
+        store.insert(b.clone());
+        assert_eq!(store.get("key", 2, &|_| 0.5), vec![b, a]);
+    }
+
+    #[test]
+    fn competing_values_are_ranked_by_trust() {
+        let (trusted, other) = (SecretId::generate(), SecretId::generate());
+        let trust = |id: &Id| if *id == trusted.id() { 0.9 } else { 0.5 };
+        let seq = SequenceNumber { time: 5, counter: 0 };
+        let a = Entry::new(&trusted, seq, "key".into(), "a".into());
+        let b = Entry::new(&other, seq, "key".into(), "b".into());
+
+        let mut store = MemoryStore::new();
+        store.insert(b.clone());
+        store.insert(a.clone());
+        assert_eq!(store.get("key", 2, &trust), vec![a.clone(), b.clone()]);
+        assert_eq!(store.get("key", 1, &trust), vec![a.clone()]);
+
+        let (winner, alternatives) = store.resolve("key", &trust);
+        assert_eq!(winner, Some(a));
+        assert_eq!(alternatives, vec![b]);
+    }
+
+    #[test]
+    fn concurrent_writes_are_settled_by_trust_whenever_they_were_made() {
+        let (trusted, other) = (SecretId::generate(), SecretId::generate());
+        let trust = |id: &Id| if *id == trusted.id() { 0.9 } else { 0.5 };
+        let mut clock = Clock::new();
+        let a = Entry::new(&trusted, clock.tick(), "key".into(), "a".into());
+        // written later, but without having seen a
+        let b = Entry::new(&other, clock.tick(), "key".into(), "b".into());
+
+        let mut store = MemoryStore::new();
+        store.insert(a.clone());
+        store.insert(b.clone());
+        assert_eq!(store.get("key", 1, &trust), vec![b.clone()]);
+        assert_eq!(store.resolve("key", &trust), (Some(a), vec![b]));
+        assert_eq!(store.state("key", &trust), Some(State::Register("a".into())));
+    }
+
+    #[test]
+    fn heads_exclude_superseded_entries() {
+        let (a, b) = (SecretId::generate(), SecretId::generate());
+        let mut clock = Clock::new();
+        let mut store = MemoryStore::new();
+
+        let base = Entry::new(&a, clock.tick(), "key".into(), "base".into());
+        store.insert(base.clone());
+        assert_eq!(store.heads("key"), vec![base.clone()]);
+
+        // two writes that both build on base conflict
+        let left = Entry::with_parents(&a, clock.tick(), "key".into(), "left".into(), vec![base.hash()]);
+        let right = Entry::with_parents(&b, clock.tick(), "key".into(), "right".into(), vec![base.hash()]);
+        store.insert(left.clone());
+        store.insert(right.clone());
+        assert_eq!(store.heads("key"), vec![right.clone(), left.clone()]);
+
+        // merging both resolves the conflict
+        let merged = Entry::with_parents(&a, clock.tick(), "key".into(), "merged".into(), vec![left.hash(), right.hash()]);
+        assert!(merged.verify());
+        store.insert(merged.clone());
+        assert_eq!(store.heads("key"), vec![merged]);
+    }
+
+    #[test]
+    fn heads_are_kept_whatever_order_entries_arrive_in() {
+        let secret = SecretId::generate();
+        let mut clock = Clock::new();
+        let base = Entry::new(&secret, clock.tick(), "key".into(), "base".into());
+        let child = Entry::with_parents(&secret, clock.tick(), "key".into(), "child".into(), vec![base.hash()]);
+
+        // the child is gossiped first
+        let mut store = MemoryStore::new();
+        store.insert(child.clone());
+        store.insert(base.clone());
+        assert_eq!(store.heads("key"), vec![child.clone()]);
+
+        // once a head expires, what it superseded is a head again
+        let mut expiring = Entry::with_parents(&secret, clock.tick(), "key".into(), "brief".into(), vec![child.hash()]);
+        expiring.expires = Some(1);
+        expiring.sign(&secret);
+        store.insert(expiring);
+        assert_eq!(store.heads("key"), vec![child.clone()]);
+        store.expire(u64::MAX);
+        assert_eq!(store.heads("key"), vec![child]);
+    }
+
+    #[test]
+    fn late_tombstones_keep_newer_entries_merged() {
+        let secret = SecretId::generate();
+        let mut clock = Clock::new();
+        let tombstone = Entry::new(&secret, clock.tick(), "count".into(), Value::Tombstone);
+        let increment = Entry::new(&secret, clock.tick(), "count".into(), Value::PnCounter(5));
+        let register = Entry::new(&secret, clock.tick(), "key".into(), "val".into());
+        let late = Entry::new(&secret, SequenceNumber::ZERO, "key".into(), Value::Tombstone);
+
+        // the tombstones are gossiped after the entries that follow them
+        let mut store = MemoryStore::new();
+        store.insert(increment.clone());
+        store.insert(tombstone);
+        store.insert(register.clone());
+        store.insert(late);
+
+        assert_eq!(store.state("count", &|_| 0.5), Some(State::Counter(5)));
+        assert_eq!(store.heads("count"), vec![increment]);
+        assert_eq!(store.heads("key"), vec![register.clone()]);
+        assert_eq!(store.resolve("key", &|_| 0.5), (Some(register), Vec::new()));
+        assert_eq!(store.keys(Unbounded, Unbounded).collect::<Vec<_>>(), vec!["count", "key"]);
+    }
+
+    #[test]
+    fn tombstones_hide_and_compaction_drops_earlier_entries() {
+        let secret = SecretId::generate();
+        let mut clock = Clock::new();
+        let mut store = MemoryStore::new();
+        store.insert(Entry::new(&secret, clock.tick(), "key".into(), "old".into()));
+        let tombstone = Entry::new(&secret, clock.tick(), "key".into(), Value::Tombstone);
+        store.insert(tombstone.clone());
+        assert!(store.get("key", 5, &|_| 0.5).is_empty());
+        assert!(store.heads("key").is_empty());
+        assert_eq!(store.state("key", &|_| 0.5), None);
+
+        let new = Entry::new(&secret, clock.tick(), "key".into(), "new".into());
+        store.insert(new.clone());
+        assert_eq!(store.get("key", 5, &|_| 0.5), vec![new.clone()]);
+
+        // the tombstone is kept until it expires
+        assert_eq!(store.compact(0), 1);
+        assert!(store.contains(&tombstone));
+        assert_eq!(store.compact(u64::MAX), 1);
+        assert_eq!(store.entries().cloned().collect::<Vec<_>>(), vec![new]);
+    }
+
+    #[test]
+    fn entries_older_than_a_compacted_tombstone_stay_deleted() {
+        let secret = SecretId::generate();
+        let mut clock = Clock::new();
+        let mut store = MemoryStore::new();
+        let old = Entry::new(&secret, clock.tick(), "key".into(), "old".into());
+        store.insert(old.clone());
+        store.insert(Entry::new(&secret, clock.tick(), "key".into(), Value::Tombstone));
+        assert_eq!(store.compact(u64::MAX), 2);
+        assert_eq!(store.entries().count(), 0);
+
+        // the old entry gossiped again does not come back
+        assert!(store.is_compacted(&old));
+        store.insert(old.clone());
+        assert!(!store.contains(&old));
+        assert!(store.get("key", 1, &|_| 0.5).is_empty());
+        assert_eq!(store.keys(Unbounded, Unbounded).count(), 0);
+
+        // but newer writes do
+        let new = Entry::new(&secret, clock.tick(), "key".into(), "new".into());
+        assert!(!store.is_compacted(&new));
+        store.insert(new.clone());
+        assert_eq!(store.get("key", 1, &|_| 0.5), vec![new]);
+
+        // and a store loaded from a snapshot keeps discarding it
+        let mut restored = MemoryStore::new();
+        for (key, seq) in store.compacted() {
+            restored.restore_compacted(key.to_string(), seq);
+        }
+        assert!(restored.is_compacted(&old));
+    }
+
+    #[test]
+    fn keys_are_listed_in_order() {
+        let secret = SecretId::generate();
+        let mut clock = Clock::new();
+        let mut store = MemoryStore::new();
+        for key in ["b/2", "a/1", "b/1", "c", "b/3"] {
+            store.insert(Entry::new(&secret, clock.tick(), key.into(), "val".into()));
+        }
+        store.insert(Entry::new(&secret, clock.tick(), "b/3".into(), Value::Tombstone));
+
+        let prefixed: Vec<_> = store
+            .keys(Included("b/"), Unbounded)
+            .take_while(|key| key.starts_with("b/"))
+            .collect();
+        // deleted keys are not listed
+        assert_eq!(prefixed, vec!["b/1", "b/2"]);
+
+        let range: Vec<_> = store.keys(Excluded("a/1"), Excluded("c")).collect();
+        assert_eq!(range, vec!["b/1", "b/2"]);
+    }
+
+    #[test]
+    fn ranges_ending_before_they_start_are_empty() {
+        let secret = SecretId::generate();
+        let mut clock = Clock::new();
+        let mut store = MemoryStore::new();
+        for key in ["a", "b", "c"] {
+            store.insert(Entry::new(&secret, clock.tick(), key.into(), "val".into()));
+        }
+
+        // as asked for by Scan { start: "b", end: Some("a") }
+        assert_eq!(store.keys(Included("b"), Excluded("a")).count(), 0);
+        // and by Scan { start: "a", end: Some("c"), after: Some("c") }
+        assert_eq!(store.keys(Excluded("c"), Excluded("c")).count(), 0);
+        assert_eq!(store.keys(Included("b"), Excluded("b")).count(), 0);
+        assert_eq!(store.keys(Included("b"), Included("b")).collect::<Vec<_>>(), vec!["b"]);
+    }
+
+    #[test]
+    fn revoked_authors_are_hidden() {
+        let secret = SecretId::generate();
+        let mut store = MemoryStore::new();
+        let entry = Entry::new(&secret, SequenceNumber::ZERO, "key".into(), "val".into());
+        store.insert(entry.clone());
+
+        store.revoke(secret.id());
+        assert!(store.is_revoked(&secret.id()));
+        assert!(store.get("key", 1, &|_| 0.5).is_empty());
+        // the entry is still held, so it is not fetched again
+        assert!(store.contains(&entry));
+    }
+
+    #[test]
+    fn entries_are_listed_by_author() {
+        let (a, b) = (SecretId::generate(), SecretId::generate());
+        let mut clock = Clock::new();
+        let mut store = MemoryStore::new();
+        let mut written = Vec::new();
+        for key in ["x", "y", "x"] {
+            let entry = Entry::new(&a, clock.tick(), key.into(), "val".into());
+            store.insert(entry.clone());
+            written.push(entry);
+        }
+        store.insert(Entry::new(&b, clock.tick(), "x".into(), "other".into()));
+
+        assert_eq!(store.by_author(&a.id(), None, 10), (written.clone(), None));
+        let (first, next) = store.by_author(&a.id(), None, 2);
+        assert_eq!(first, written[..2]);
+        assert_eq!(next, Some(Cursor::after(&written[1])));
+        assert_eq!(store.by_author(&a.id(), next, 2), (written[2..].to_vec(), None));
+
+        store.revoke(a.id());
+        assert!(store.by_author(&a.id(), None, 10).0.is_empty());
+    }
+
+    #[test]
+    fn changes_include_deletions_and_page_through_concurrent_writes() {
+        let (a, b) = (SecretId::generate(), SecretId::generate());
+        let mut clock = Clock::new();
+        let mut store = MemoryStore::new();
+        let first = Entry::new(&a, clock.tick(), "key".into(), "a".into());
+        let seq = clock.tick();
+        let mut concurrent = [
+            Entry::new(&a, seq, "key".into(), "b".into()),
+            Entry::new(&b, seq, "key".into(), "c".into()),
+        ];
+        concurrent.sort_by_key(|entry| entry.id);
+        let tombstone = Entry::new(&a, clock.tick(), "key".into(), Value::Tombstone);
+        for entry in [&first, &concurrent[0], &concurrent[1], &tombstone] {
+            store.insert(entry.clone());
+        }
+
+        // a page can end between entries with the same sequence number, so it stays within its limit
+        let (page, next) = store.changes("key", Some(first.seq.into()), 1);
+        assert_eq!(page, concurrent[..1]);
+        assert_eq!(next, Some(Cursor::after(&concurrent[0])));
+        let (page, next) = store.changes("key", next, 1);
+        assert_eq!(page, concurrent[1..]);
+        assert_eq!(store.changes("key", next, 1), (vec![tombstone], None));
+    }
+
+    #[test]
+    fn changes_carry_whole_batches() {
+        let secret = SecretId::generate();
+        let mut clock = Clock::new();
+        let seq = clock.tick();
+        let batch: Vec<_> = ["a", "b"]
+            .into_iter()
+            .map(|key| {
+                let mut entry = Entry::new(&secret, seq, key.into(), "val".into());
+                entry.batch = 2;
+                entry.sign(&secret);
+                entry
+            })
+            .collect();
+        let mut store = MemoryStore::new();
+        store.ingest(batch.clone());
+        let later = Entry::new(&secret, clock.tick(), "b".into(), "later".into());
+        store.insert(later.clone());
+
+        let (page, next) = store.changes("a", None, 10);
+        assert_eq!((&page, &next), (&batch, &None));
+
+        // a batch is not split even when it is bigger than a page
+        let (first, after) = store.by_author(&secret.id(), None, 1);
+        assert_eq!((&first, &after), (&batch, &Some(Cursor::after(&batch[1]))));
+        assert_eq!(store.by_author(&secret.id(), after, 1), (vec![later], None));
+
+        // and the receiving end keeps the batch
+        let msg = Message::changes(secret.id(), page, next);
+        let MessageType::Changes { mut entries, .. } = Message::deserialize(&msg.serialize()).unwrap().take_msg_type() else {
+            panic!("should decode as Changes");
+        };
+        Entry::retain_complete_batches(&mut entries);
+        assert_eq!(entries, batch);
+    }
+
+    #[test]
+    fn sequence_numbers_round_trip_as_text() {
+        let seq = SequenceNumber { time: 1700000000000, counter: 3 };
+        assert_eq!(seq.to_string(), "1700000000000.3");
+        assert_eq!("1700000000000.3".parse(), Ok(seq));
+        assert_eq!("5".parse(), Ok(SequenceNumber { time: 5, counter: 0 }));
+    }
+
+    #[test]
+    fn cursors_round_trip_as_text() {
+        let secret = SecretId::generate();
+        let entry = Entry::new(&secret, SequenceNumber { time: 5, counter: 1 }, "a/b".into(), "val".into());
+        let cursor = Cursor::after(&entry);
+        assert_eq!(cursor.to_string(), format!("5.1/{}/a/b", secret.id()));
+        assert_eq!(cursor.to_string().parse(), Ok(cursor));
+        assert_eq!("5.1".parse(), Ok(Cursor::from(entry.seq)));
+        assert!("5.1/nonsense/key".parse::<Cursor>().is_err());
+    }
+
+    #[test]
+    fn expired_entries_are_hidden_and_swept() {
+        let secret = SecretId::generate();
+        let mut clock = Clock::new();
+        let mut store = MemoryStore::new();
+        let lasting = Entry::new(&secret, clock.tick(), "key".into(), "lasting".into());
+        store.insert(lasting.clone());
+
+        let mut ephemeral = Entry::new(&secret, clock.tick(), "key".into(), "ephemeral".into());
+        ephemeral.expires = Some(ephemeral.seq.time + 60_000);
+        ephemeral.sign(&secret);
+        assert!(ephemeral.verify());
+        store.insert(ephemeral.clone());
+        assert_eq!(store.get("key", 1, &|_| 0.5), vec![ephemeral.clone()]);
+
+        let mut gone = Entry::new(&secret, clock.tick(), "gone".into(), "val".into());
+        gone.expires = Some(1);
+        gone.sign(&secret);
+        store.insert(gone.clone());
+        assert!(store.get("gone", 1, &|_| 0.5).is_empty());
+        assert_eq!(store.keys(Unbounded, Unbounded).collect::<Vec<_>>(), vec!["key"]);
+        assert!(!store.by_author(&secret.id(), None, 10).0.contains(&gone));
+        assert!(store.changes("gone", None, 10).0.is_empty());
+
+        // once the ephemeral entry expires the lasting one is read again
+        let later = ephemeral.seq.time + 60_000;
+        assert_eq!(store.expire(later), 2);
+        assert!(!store.contains(&gone));
+        assert_eq!(store.entries().cloned().collect::<Vec<_>>(), vec![lasting]);
+        assert!(store.by_author(&secret.id(), None, 10).0.iter().all(|entry| entry.expires.is_none()));
+    }
+
+    #[test]
+    fn entries_compacted_away_are_not_swept_again() {
+        let secret = SecretId::generate();
+        let mut clock = Clock::new();
+        let mut store = MemoryStore::new();
+        let mut ephemeral = Entry::new(&secret, clock.tick(), "key".into(), "ephemeral".into());
+        ephemeral.expires = Some(ephemeral.seq.time + 60_000);
+        ephemeral.sign(&secret);
+        store.insert(ephemeral.clone());
+        store.insert(Entry::new(&secret, clock.tick(), "key".into(), Value::Tombstone));
+
+        assert_eq!(store.compact(0), 1);
+        let again = Entry::new(&secret, clock.tick(), "key".into(), "again".into());
+        store.insert(again.clone());
+        assert_eq!(store.expire(u64::MAX), 0);
+        assert_eq!(store.get("key", 5, &|_| 0.5), vec![again]);
+    }
+}
@@ -0,0 +1,89 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use ddb_lib::{Clock, Entry, MemoryStore, SecretId, State, Store, Value};
+
+    #[test]
+    fn concurrent_increments_are_all_counted() {
+        let (a, b) = (SecretId::generate(), SecretId::generate());
+        let mut clock = Clock::new();
+        let mut store = MemoryStore::new();
+        store.insert(Entry::new(&a, clock.tick(), "count".into(), Value::GCounter(3)));
+        store.insert(Entry::new(&b, clock.tick(), "count".into(), Value::PnCounter(-1)));
+        store.insert(Entry::new(&a, clock.tick(), "count".into(), Value::PnCounter(4)));
+
+        assert_eq!(store.state("count", &|_| 0.5), Some(State::Counter(6)));
+        assert_eq!(store.state("missing", &|_| 0.5), None);
+    }
+
+    #[test]
+    fn merged_counts_follow_revocations_deletes_and_expiry() {
+        let (a, b) = (SecretId::generate(), SecretId::generate());
+        let mut clock = Clock::new();
+        let mut store = MemoryStore::new();
+        store.insert(Entry::new(&a, clock.tick(), "count".into(), Value::GCounter(3)));
+        store.insert(Entry::new(&b, clock.tick(), "count".into(), Value::PnCounter(2)));
+        assert_eq!(store.state("count", &|_| 0.5), Some(State::Counter(5)));
+
+        store.revoke(b.id());
+        assert_eq!(store.state("count", &|_| 0.5), Some(State::Counter(3)));
+
+        store.insert(Entry::new(&a, clock.tick(), "count".into(), Value::Tombstone));
+        assert_eq!(store.state("count", &|_| 0.5), None);
+        store.insert(Entry::new(&a, clock.tick(), "count".into(), Value::PnCounter(1)));
+        assert_eq!(store.state("count", &|_| 0.5), Some(State::Counter(1)));
+
+        // an expired increment stops counting before it is swept
+        let mut expired = Entry::new(&a, clock.tick(), "count".into(), Value::PnCounter(10));
+        expired.expires = Some(1);
+        expired.sign(&a);
+        store.insert(expired);
+        assert_eq!(store.state("count", &|_| 0.5), Some(State::Counter(1)));
+        store.expire(u64::MAX);
+        assert_eq!(store.state("count", &|_| 0.5), Some(State::Counter(1)));
+    }
+
+    #[test]
+    fn removes_only_take_out_observed_adds() {
+        let (a, b) = (SecretId::generate(), SecretId::generate());
+        let mut clock = Clock::new();
+        let mut store = MemoryStore::new();
+        store.insert(Entry::new(&a, clock.tick(), "set".into(), Value::SetAdd("x".into())));
+        store.insert(Entry::new(&a, clock.tick(), "set".into(), Value::SetAdd("y".into())));
+
+        // b removes x, while a concurrently adds x again without having seen the remove
+        let observed = State::observed_adds(&store.get("set", usize::MAX, &|_| 0.5), "x");
+        let concurrent_add = Entry::new(&a, clock.tick(), "set".into(), Value::SetAdd("x".into()));
+        store.insert(Entry::new(
+            &b,
+            clock.tick(),
+            "set".into(),
+            Value::SetRemove { elem: "x".into(), observed },
+        ));
+        let expected: BTreeSet<String> = ["y".to_string()].into();
+        assert_eq!(store.state("set", &|_| 0.5), Some(State::Set(expected)));
+
+        store.insert(concurrent_add);
+        let expected: BTreeSet<String> = ["x".to_string(), "y".to_string()].into();
+        assert_eq!(store.state("set", &|_| 0.5), Some(State::Set(expected)));
+    }
+
+    #[test]
+    fn registers_keep_concurrent_writes_only_when_multi_valued() {
+        let (a, b) = (SecretId::generate(), SecretId::generate());
+        let mut clock = Clock::new();
+        let mut store = MemoryStore::new();
+        store.insert(Entry::new(&a, clock.tick(), "lww".into(), "old".into()));
+        store.insert(Entry::new(&b, clock.tick(), "lww".into(), "new".into()));
+        assert_eq!(store.state("lww", &|_| 0.5), Some(State::Register("new".into())));
+
+        let trust = |id: &ddb_lib::Id| if *id == a.id() { 0.9 } else { 0.5 };
+        store.insert(Entry::new(&a, clock.tick(), "mv".into(), Value::Mv("first".into())));
+        store.insert(Entry::new(&b, clock.tick(), "mv".into(), Value::Mv("second".into())));
+        assert_eq!(
+            store.state("mv", &trust),
+            Some(State::Values(vec!["first".into(), "second".into()]))
+        );
+    }
+}
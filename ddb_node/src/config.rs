@@ -1,29 +1,97 @@
 use std::{
     fs,
     net::{Ipv4Addr, SocketAddr, SocketAddrV4},
-    path::Path,
+    path::{Path, PathBuf},
 };
 
+use ddb_lib::Id;
+
+/// Most bits of proof-of-work a config may ask for, minting a stamp takes around `2^n` hashes
+/// so a node asking for more would not start for hours
+pub static MAX_POW_DIFFICULTY: u32 = 32;
+
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
 pub struct Config {
     bind_addr: SocketAddr,
+    /// Directory holding the node's key file and other persistent state
+    data_dir: PathBuf,
+    /// Ids, besides the node's own, allowed to send control messages (Set, Batch, CompareAndSet, Delete, Link, Trust)
+    operators: Vec<Id>,
+    /// Bits of proof-of-work a node's stamp needs before it can be our neighbor
+    pow_difficulty: u32,
+    /// Where the node keeps its data
+    storage: Storage,
+}
+
+/// The backends a node can keep its data in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Storage {
+    /// Kept only in memory, lost when the node stops
+    Memory,
+    /// Persisted to a log in the data directory
+    File,
 }
 
 impl Config {
     pub fn load(path: &Path) -> Self {
         let data = fs::read_to_string(path).expect("config path should be openable");
-		toml::from_str(&data).expect("config file should be formatted correctly")
+		let config: Self = toml::from_str(&data).expect("config file should be formatted correctly");
+		assert!(
+			config.pow_difficulty <= MAX_POW_DIFFICULTY,
+			"pow_difficulty should be at most {MAX_POW_DIFFICULTY}, not {}",
+			config.pow_difficulty
+		);
+		config
     }
 
 	pub fn bind_addr(&self) -> &SocketAddr {
 		&self.bind_addr
 	}
+
+	pub fn set_bind_addr(&mut self, bind_addr: SocketAddr) {
+		self.bind_addr = bind_addr;
+	}
+
+	pub fn data_dir(&self) -> &Path {
+		&self.data_dir
+	}
+
+	pub fn set_data_dir(&mut self, data_dir: PathBuf) {
+		self.data_dir = data_dir;
+	}
+
+	pub fn operators(&self) -> &[Id] {
+		&self.operators
+	}
+
+	pub fn set_operators(&mut self, operators: Vec<Id>) {
+		self.operators = operators;
+	}
+
+	pub fn pow_difficulty(&self) -> u32 {
+		self.pow_difficulty
+	}
+
+	/// Sets the proof-of-work required from neighbors, at most `MAX_POW_DIFFICULTY`
+	pub fn set_pow_difficulty(&mut self, pow_difficulty: u32) {
+		self.pow_difficulty = pow_difficulty.min(MAX_POW_DIFFICULTY);
+	}
+
+	pub fn storage(&self) -> Storage {
+		self.storage
+	}
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             bind_addr:  SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 2000)),
+            data_dir: PathBuf::from("ddb_data"),
+            operators: Vec::new(),
+            pow_difficulty: 16,
+            storage: Storage::File,
         }
     }
 }
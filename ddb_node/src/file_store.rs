@@ -0,0 +1,169 @@
+use std::{io, ops::Bound, path::Path};
+
+use ddb_lib::{Cursor, Entry, Id, MemoryStore, SequenceNumber, Store, Trust};
+
+use crate::journal::{Journal, Record};
+
+/// Number of logged changes after which the data is snapshotted during upkeep
+static SNAPSHOT_AFTER: usize = 1000;
+
+/// A store kept in memory and persisted to a journal on disk
+pub struct FileStore {
+    memory: MemoryStore,
+    journal: Journal,
+    /// Whether a change could not be logged, so the next upkeep snapshots the data instead
+    unlogged: bool,
+}
+
+impl FileStore {
+    /// Loads the data persisted in `dir`, and persists all further changes there
+    pub fn open(dir: &Path) -> io::Result<Self> {
+        let (journal, snapshot, records) = Journal::open(dir)?;
+        let mut memory = MemoryStore::new();
+        for (key, seq) in snapshot.compacted {
+            memory.restore_compacted(key, seq);
+        }
+        for entry in snapshot.entries {
+            apply(&mut memory, Record::Insert(Box::new(entry)));
+        }
+        for id in snapshot.revoked {
+            apply(&mut memory, Record::Revoke(id));
+        }
+        for record in records {
+            apply(&mut memory, record);
+        }
+        Ok(Self {
+            memory,
+            journal,
+            unlogged: false,
+        })
+    }
+
+    /// Persists a change, then applies it.
+    ///
+    /// A change that could not be logged is still applied, and persisted by a snapshot at the next upkeep.
+    fn record(&mut self, record: Record) {
+        if let Err(err) = self.journal.append(&record) {
+            eprintln!("failed to log data change: {err}");
+            self.unlogged = true;
+        }
+        apply(&mut self.memory, record);
+    }
+
+    /// Replaces the log with a snapshot of everything held in memory
+    fn snapshot(&mut self) {
+        let entries = self.memory.entries().cloned().collect();
+        let revoked = self.memory.revoked().copied().collect();
+        let compacted = self
+            .memory
+            .compacted()
+            .map(|(key, seq)| (key.to_string(), seq))
+            .collect();
+        match self.journal.snapshot(entries, revoked, compacted) {
+            Ok(()) => self.unlogged = false,
+            Err(err) => eprintln!("failed to snapshot data: {err}"),
+        }
+    }
+}
+
+fn apply(memory: &mut MemoryStore, record: Record) {
+    match record {
+        Record::Insert(entry) => memory.insert(*entry),
+        Record::Ingest(entries) => memory.ingest(entries),
+        Record::Revoke(id) => memory.revoke(id),
+        Record::Expire(now) => {
+            memory.expire(now);
+        }
+    }
+}
+
+impl Store for FileStore {
+    fn insert(&mut self, entry: Entry) {
+        self.record(Record::Insert(Box::new(entry)));
+    }
+
+    fn ingest(&mut self, entries: Vec<Entry>) {
+        if !entries.is_empty() {
+            self.record(Record::Ingest(entries));
+        }
+    }
+
+    fn contains(&self, entry: &Entry) -> bool {
+        self.memory.contains(entry)
+    }
+
+    fn is_compacted(&self, entry: &Entry) -> bool {
+        self.memory.is_compacted(entry)
+    }
+
+    fn get(&self, key: &str, count: usize, trust: Trust) -> Vec<Entry> {
+        self.memory.get(key, count, trust)
+    }
+
+    fn heads(&self, key: &str) -> Vec<Entry> {
+        self.memory.heads(key)
+    }
+
+    fn by_author(&self, author: &Id, after: Option<Cursor>, limit: usize) -> (Vec<Entry>, Option<Cursor>) {
+        self.memory.by_author(author, after, limit)
+    }
+
+    fn changes(&self, key: &str, after: Option<Cursor>, limit: usize) -> (Vec<Entry>, Option<Cursor>) {
+        self.memory.changes(key, after, limit)
+    }
+
+    fn keys<'a>(&'a self, from: Bound<&str>, to: Bound<&str>) -> Box<dyn Iterator<Item = &'a str> + 'a> {
+        self.memory.keys(from, to)
+    }
+
+    fn revoke(&mut self, id: Id) {
+        if !self.memory.is_revoked(&id) {
+            self.record(Record::Revoke(id));
+        }
+    }
+
+    fn is_revoked(&self, id: &Id) -> bool {
+        self.memory.is_revoked(id)
+    }
+
+    fn entries(&self) -> Box<dyn Iterator<Item = &Entry> + '_> {
+        self.memory.entries()
+    }
+
+    fn revoked(&self) -> Box<dyn Iterator<Item = &Id> + '_> {
+        self.memory.revoked()
+    }
+
+    fn compacted(&self) -> Box<dyn Iterator<Item = (&str, SequenceNumber)> + '_> {
+        self.memory.compacted()
+    }
+
+    fn compact(&mut self, expiry: u64) -> usize {
+        let dropped = self.memory.compact(expiry);
+        // the log still holds the dropped entries, replace it
+        if dropped > 0 {
+            self.snapshot();
+        }
+        dropped
+    }
+
+    fn expire(&mut self, now: u64) -> usize {
+        let dropped = self.memory.expire(now);
+        // replaying the sweep drops the same entries, the next snapshot leaves them out for good
+        if dropped > 0
+            && let Err(err) = self.journal.append(&Record::Expire(now))
+        {
+            eprintln!("failed to log expired data: {err}");
+            self.unlogged = true;
+        }
+        dropped
+    }
+
+    /// Snapshots the data once enough changes have been logged, so the log does not grow forever,
+    /// or once a change could not be logged
+    fn maintain(&mut self) {
+        if self.unlogged || self.journal.records() >= SNAPSHOT_AFTER {
+            self.snapshot();
+        }
+    }
+}
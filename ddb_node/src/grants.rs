@@ -0,0 +1,97 @@
+use std::{
+    collections::HashSet,
+    io,
+    path::{Path, PathBuf},
+};
+
+use ddb_lib::{Capability, Id, key_owner};
+
+use crate::saved;
+
+/// Name of the granted capabilities within the data directory
+static GRANTS_FILE: &str = "grants.json";
+static GRANTS_VERSION: u32 = 1;
+/// Most chains kept, grants are taken from anyone so they must not grow without bound
+static MAX_GRANTS: usize = 256;
+
+/// The grants as saved to disk
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+struct GrantsFile {
+    chains: Vec<Vec<Capability>>,
+}
+
+/// Delegation chains letting this node write under other authors' namespaces
+pub struct Grants {
+    path: PathBuf,
+    us: Id,
+    chains: HashSet<Vec<Capability>>,
+}
+
+impl Grants {
+    /// Loads the grants saved in `dir`, dropping those that have expired
+    pub fn open(us: Id, dir: &Path, now: u64) -> io::Result<Self> {
+        let path = dir.join(GRANTS_FILE);
+        let file: GrantsFile = saved::load(&path, GRANTS_VERSION)?;
+
+        let mut grants = Self {
+            path,
+            us,
+            chains: HashSet::new(),
+        };
+        grants.chains = file
+            .chains
+            .into_iter()
+            .filter(|chain| grants.is_valid(chain, now))
+            .take(MAX_GRANTS)
+            .collect();
+        Ok(grants)
+    }
+
+    /// Keeps a chain granted to us, returns false if it does not grant us anything or too many are kept already
+    pub fn add(&mut self, chain: Vec<Capability>, now: u64) -> bool {
+        if !self.is_valid(&chain, now) {
+            return false;
+        }
+        if self.chains.contains(&chain) {
+            return true;
+        }
+        // make room by dropping the chains that have run out
+        self.chains
+            .retain(|chain| chain.iter().all(|capability| capability.expiry >= now));
+        if self.chains.len() >= MAX_GRANTS {
+            return false;
+        }
+        self.chains.insert(chain);
+        self.save();
+        true
+    }
+
+    /// A chain that lets us write `key` at `time`, the one lasting longest if there are several
+    pub fn chain_for(&self, key: &str, time: u64) -> Option<Vec<Capability>> {
+        let owner = key_owner(key)?;
+        self.chains
+            .iter()
+            .filter(|chain| Capability::authorises(chain, &owner, &self.us, key, time))
+            .max_by_key(|chain| chain.iter().map(|capability| capability.expiry).min())
+            .cloned()
+    }
+
+    /// Checks that a chain ends with us and starts with the owner of the namespace it grants
+    fn is_valid(&self, chain: &[Capability], now: u64) -> bool {
+        let (Some(first), Some(last)) = (chain.first(), chain.last()) else {
+            return false;
+        };
+        key_owner(&last.prefix) == Some(first.grantor)
+            && Capability::authorises(chain, &first.grantor, &self.us, &last.prefix, now)
+    }
+
+    /// Saves the grants in place of the last save
+    fn save(&self) {
+        let file = GrantsFile {
+            chains: self.chains.iter().cloned().collect(),
+        };
+        if let Err(err) = saved::save(&self.path, GRANTS_VERSION, &file) {
+            eprintln!("failed to save grants: {err}");
+        }
+    }
+}
@@ -1,15 +1,41 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    path::{Path, PathBuf},
+};
 
-use ddb_lib::Id;
+use ddb_lib::{Id, KeyChange, KeyEvent};
+
+use crate::saved;
 
 static DEFAULT_TRUST: f32 = 0.5;
 static TRUSTED_LEVEL: f32 = 0.75;
 static DISTRUSTED_LEVEL: f32 = 0.25;
+/// Name of the trust file within the data directory
+static TRUST_FILE: &str = "trust.json";
+static TRUST_VERSION: u32 = 1;
+
+/// Trust state as saved to disk
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+struct TrustFile {
+    base_trust: HashMap<Id, f32>,
+    trust_offset: HashMap<Id, HashMap<Id, f32>>,
+    successors: HashMap<Id, Id>,
+    revoked: HashSet<Id>,
+}
 
 pub struct Identification {
     us: Id,
     base_trust: HashMap<Id, f32>,
     trust_offset: HashMap<Id, HashMap<Id, f32>>,
+    /// Retired keys and the keys that replaced them
+    successors: HashMap<Id, Id>,
+    /// Keys that have announced their own revocation
+    revoked: HashSet<Id>,
+    /// Where trust is saved on every change, if anywhere
+    path: Option<PathBuf>,
+    /// Whether there are changes waiting for `flush`
+    unsaved: bool,
 }
 
 impl Identification {
@@ -18,6 +44,48 @@ impl Identification {
             us,
             base_trust: HashMap::new(),
             trust_offset: HashMap::new(),
+            successors: HashMap::new(),
+            revoked: HashSet::new(),
+            path: None,
+            unsaved: false,
+        }
+    }
+
+    /// Loads the trust saved in `dir`, and saves all further changes there
+    pub fn open(us: Id, dir: &Path) -> io::Result<Self> {
+        let path = dir.join(TRUST_FILE);
+        let file: TrustFile = saved::load(&path, TRUST_VERSION)?;
+        Ok(Self {
+            base_trust: file.base_trust,
+            trust_offset: file.trust_offset,
+            successors: file.successors,
+            revoked: file.revoked,
+            path: Some(path),
+            ..Self::new(us)
+        })
+    }
+
+    /// Saves the trust state, if it has a place to be saved
+    fn save(&mut self) {
+        self.unsaved = false;
+        let Some(path) = self.path.as_ref() else {
+            return;
+        };
+        let file = TrustFile {
+            base_trust: self.base_trust.clone(),
+            trust_offset: self.trust_offset.clone(),
+            successors: self.successors.clone(),
+            revoked: self.revoked.clone(),
+        };
+        if let Err(err) = saved::save(path, TRUST_VERSION, &file) {
+            eprintln!("failed to save trust: {err}");
+        }
+    }
+
+    /// Saves trust levels reported by others since the last save, see `adjust_offset`
+    pub fn flush(&mut self) {
+        if self.unsaved {
+            self.save();
         }
     }
 
@@ -31,25 +99,109 @@ impl Identification {
         base_trust + trust_offset
     }
 
+    /// Trust used to rank competing values, our own values rank above everyone else's
+    pub fn rank(&self, of: &Id) -> f32 {
+        if self.is_us(of) {
+            return f32::INFINITY;
+        }
+        self.get_trust(of)
+    }
+
     pub fn change_trust(&mut self, id: Id, delta: f32) {
+        let id = self.current_key(id);
         if self.is_us(&id) {
             return;
         }
         let trust = self.base_trust.entry(id).or_insert(DEFAULT_TRUST);
         *trust = (*trust + delta).clamp(0.0, 1.0);
+        self.save();
     }
 
     pub fn is_trusted(&self, id: &Id) -> bool {
         self.get_trust(id) >= TRUSTED_LEVEL
     }
 
-    pub fn is_neutral(&self, id: &Id) -> bool {
-        let trust = self.get_trust(id);
-        trust < TRUSTED_LEVEL && trust > DISTRUSTED_LEVEL
+    pub fn is_distrusted(&self, id: &Id) -> bool {
+        self.is_revoked(id) || self.get_trust(id) <= DISTRUSTED_LEVEL
     }
 
-    pub fn is_distrusted(&self, id: &Id) -> bool {
-        self.get_trust(id) <= DISTRUSTED_LEVEL
+    pub fn is_revoked(&self, id: &Id) -> bool {
+        self.revoked.contains(id)
+    }
+
+    /// Follows rotations of a key to the key currently in use
+    pub fn current_key(&self, id: Id) -> Id {
+        let mut current = id;
+        // bounded, in case a cycle of rotations has been announced
+        for _ in 0..self.successors.len() {
+            match self.successors.get(&current) {
+                Some(successor) => current = *successor,
+                None => break,
+            }
+        }
+        current
+    }
+
+    /// Applies a verified key event, returns true if it was new to us.
+    ///
+    /// A rotated key hands its trust, and the trust it placed in others, to its successor.
+    /// Only the first rotation of a key is accepted, and revoked keys cannot be rotated.
+    pub fn apply_key_event(&mut self, event: &KeyEvent) -> bool {
+        if self.is_us(&event.id) || self.is_revoked(&event.id) {
+            return false;
+        }
+        match &event.change {
+            KeyChange::Revoke => {
+                self.revoked.insert(event.id);
+            }
+            KeyChange::Rotate { successor, .. } => {
+                if self.successors.contains_key(&event.id) {
+                    return false;
+                }
+                self.successors.insert(event.id, *successor);
+                self.migrate_trust(&event.id, successor);
+            }
+        }
+        self.save();
+        true
+    }
+
+    /// Hands the trust of a rotated key to its successor.
+    ///
+    /// Where the successor is already known, the lower trust is kept, so rotating into a key can
+    /// never raise the trust it had. Levels the successor reported itself are newer than those
+    /// of the key it replaces, so they are kept, and neither key may vouch for the successor.
+    fn migrate_trust(&mut self, old: &Id, new: &Id) {
+        if let Some(trust) = self.base_trust.remove(old) {
+            self.base_trust
+                .entry(*new)
+                .and_modify(|known| *known = known.min(trust))
+                .or_insert(trust);
+        }
+
+        // trust others have placed in the old key
+        if let Some(trustors) = self.trust_offset.remove(old) {
+            let new_trustors = self.trust_offset.entry(*new).or_default();
+            for (trustor, level) in trustors {
+                if trustor == *new {
+                    continue;
+                }
+                new_trustors
+                    .entry(trustor)
+                    .and_modify(|known| *known = known.min(level))
+                    .or_insert(level);
+            }
+        }
+
+        // trust the old key has placed in others
+        for (of, trustors) in self.trust_offset.iter_mut() {
+            let Some(level) = trustors.remove(old) else {
+                continue;
+            };
+            if of != new && !trustors.contains_key(new) {
+                trustors.insert(*new, level);
+            }
+        }
     }
 
     pub fn get_offset(&self, of: &Id) -> f32 {
@@ -64,9 +216,15 @@ impl Identification {
         })
     }
 
+    /// Records the trust `from` reports placing in `of`.
+    ///
+    /// Peers report their trust often, so this is saved by the next `flush` rather than straight away.
     pub fn adjust_offset(&mut self, from: Id, of: Id, level: f32) {
+        let (from, of) = (self.current_key(from), self.current_key(of));
         let trustors = self.trust_offset.entry(of).or_default();
-        trustors.insert(from, level);
+        if trustors.insert(from, level) != Some(level) {
+            self.unsaved = true;
+        }
     }
 
     pub fn base_trust(&self) -> impl Iterator<Item = (&Id, &f32)> {
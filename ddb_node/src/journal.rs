@@ -0,0 +1,126 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use ddb_lib::{Entry, Id, SequenceNumber};
+
+use crate::saved;
+
+/// Name of the append-only log within the data directory
+static LOG_FILE: &str = "data.log";
+/// Name of the latest snapshot within the data directory
+static SNAPSHOT_FILE: &str = "data.snapshot";
+static SNAPSHOT_VERSION: u32 = 1;
+
+/// A change to the data, as written to the log
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub enum Record {
+    Insert(Box<Entry>),
+    /// Entries inserted together, such as a batch, so a crash keeps all of them or none
+    Ingest(Vec<Entry>),
+    Revoke(Id),
+    /// Entries that expired by this time were swept, see `Store::expire`
+    Expire(u64),
+}
+
+/// Everything needed to rebuild the data without replaying the log
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct Snapshot {
+    pub entries: Vec<Entry>,
+    pub revoked: Vec<Id>,
+    /// Keys whose tombstones were compacted away, see `Store::compacted`
+    #[serde(default)]
+    pub compacted: Vec<(String, SequenceNumber)>,
+}
+
+/// Durable storage for the data: an append-only log of records plus periodic snapshots.
+///
+/// Each record is one line of json, flushed to disk before the write is acknowledged.
+/// A torn final line left by a crash is discarded on load. Snapshots are written to a
+/// temporary file and renamed into place before the log is truncated, so a crash at any
+/// point leaves either the old or the new snapshot, and replaying the log over either is safe.
+pub struct Journal {
+    dir: PathBuf,
+    log: File,
+    /// Records appended since the last snapshot
+    records: usize,
+}
+
+impl Journal {
+    /// Opens the journal in `dir`, returning the last snapshot and the records logged after it
+    pub fn open(dir: &Path) -> io::Result<(Self, Snapshot, Vec<Record>)> {
+        fs::create_dir_all(dir)?;
+
+        let snapshot: Snapshot = saved::load(&dir.join(SNAPSHOT_FILE), SNAPSHOT_VERSION)?;
+
+        let mut log = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(dir.join(LOG_FILE))?;
+
+        // replay complete records, stopping at the first one that was not fully written
+        let mut records = Vec::new();
+        let mut valid_len = 0;
+        let mut reader = BufReader::new(&log);
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 0 {
+            let Some(record) = line
+                .strip_suffix('\n')
+                .and_then(|line| serde_json::from_str(line).ok())
+            else {
+                break;
+            };
+            records.push(record);
+            valid_len += line.len() as u64;
+            line.clear();
+        }
+        if valid_len < log.metadata()?.len() {
+            log.set_len(valid_len)?;
+            log.seek(SeekFrom::End(0))?;
+        }
+
+        let journal = Self {
+            dir: dir.to_path_buf(),
+            log,
+            records: records.len(),
+        };
+        Ok((journal, snapshot, records))
+    }
+
+    pub fn append(&mut self, record: &Record) -> io::Result<()> {
+        let mut line = serde_json::to_vec(record).expect("should be serializable");
+        line.push(b'\n');
+        self.log.write_all(&line)?;
+        self.log.sync_data()?;
+        self.records += 1;
+        Ok(())
+    }
+
+    /// Records appended since the last snapshot
+    pub fn records(&self) -> usize {
+        self.records
+    }
+
+    /// Replaces the snapshot and empties the log
+    pub fn snapshot(
+        &mut self,
+        entries: Vec<Entry>,
+        revoked: Vec<Id>,
+        compacted: Vec<(String, SequenceNumber)>,
+    ) -> io::Result<()> {
+        let snapshot = Snapshot {
+            entries,
+            revoked,
+            compacted,
+        };
+        saved::save(&self.dir.join(SNAPSHOT_FILE), SNAPSHOT_VERSION, &snapshot)?;
+
+        self.log.set_len(0)?;
+        self.log.sync_all()?;
+        self.records = 0;
+        Ok(())
+    }
+}
@@ -0,0 +1,89 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use ddb_lib::SecretId;
+
+/// Name of the node's secret key file within the data directory
+pub static KEY_FILE: &str = "node.key";
+
+/// Loads the node's identity from its key file, creating one on first run.
+///
+/// If `new_identity` is set, any existing key file is moved aside to `node.key.old`, or
+/// `node.key.old.1` and so on if that is taken, and a fresh identity is generated in its place.
+pub fn load_or_create(data_dir: &Path, new_identity: bool) -> io::Result<SecretId> {
+    fs::create_dir_all(data_dir)?;
+    let path = data_dir.join(KEY_FILE);
+
+    if new_identity && path.exists() {
+        fs::rename(&path, retired_path(&path))?;
+    }
+
+    if let Some(secret) = load(data_dir)? {
+        return Ok(secret);
+    }
+    let secret = SecretId::generate();
+    write_key(&path, &secret)?;
+    Ok(secret)
+}
+
+/// Loads the node's identity from its key file, or None if it has no key file yet
+pub fn load(data_dir: &Path) -> io::Result<Option<SecretId>> {
+    let path = data_dir.join(KEY_FILE);
+    match fs::read_to_string(&path) {
+        Ok(data) => {
+            check_permissions(&path)?;
+            data.parse()
+                .map(Some)
+                .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))
+        }
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// A name for a retired key file that no earlier retired key has, so none of them is lost
+fn retired_path(path: &Path) -> PathBuf {
+    std::iter::once(path.with_extension("key.old"))
+        .chain((1..).map(|n| path.with_extension(format!("key.old.{n}"))))
+        .find(|candidate| !candidate.exists())
+        .expect("some name should be free")
+}
+
+fn write_key(path: &Path, secret: &SecretId) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    writeln!(file, "{}", secret.to_hex())?;
+    file.sync_all()
+}
+
+/// The key file must not be readable or writable by anyone but its owner
+#[cfg(unix)]
+fn check_permissions(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = fs::metadata(path)?.permissions().mode();
+    if mode & 0o077 != 0 {
+        return Err(io::Error::new(
+            ErrorKind::PermissionDenied,
+            format!(
+                "key file {} has mode {:o}, it should only be accessible by its owner (chmod 600)",
+                path.display(),
+                mode & 0o777
+            ),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_permissions(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,16 @@
+mod config;
+pub use config::{Config, MAX_POW_DIFFICULTY, Storage};
+mod file_store;
+pub use file_store::FileStore;
+mod grants;
+pub use grants::Grants;
+mod identification;
+pub use identification::Identification;
+mod journal;
+pub use journal::{Journal, Record, Snapshot};
+pub mod keyfile;
+mod node;
+pub use node::Node;
+mod peer_cache;
+pub use peer_cache::{MAX_PEERS, PeerCache, PeerRecord};
+pub mod saved;
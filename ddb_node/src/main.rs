@@ -1,34 +1,64 @@
-use std::{env::args, net::SocketAddr};
+use std::{env::args, net::SocketAddr, path::PathBuf, process::exit};
 
-use ddb_lib::Id;
+use ddb_lib::KeyEvent;
+use ddb_node::{Config, Node, keyfile};
 
-mod config;
-mod node;
-use node::Node;
-
-use crate::config::Config;
-mod data;
-mod identification;
+static USAGE: &str = "usage: ddb_node [--config <path>] [--data-dir <path>] [--new-identity] [<bind address>]";
 
 fn main() {
-    // let config = if let Some(path) = args().nth(1) {
-    //     let buf = PathBuf::from(path);
-    //     config::Config::load(&buf)
-    // } else {
-    //     config::Config::default()
-    // };
-    let config = Config::default();
-    let mut listen_addr = config.bind_addr();
-
-
-    let val = args().nth(1).map(|cmd_addr|{cmd_addr.parse::<SocketAddr>().expect("invalid argument format")});
-    if val.is_some() {
-        listen_addr = val.as_ref().unwrap();
+    let mut config = None;
+    let mut bind_addr = None;
+    let mut data_dir = None;
+    let mut new_identity = false;
+
+    let mut args = args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => {
+                let path = args.next().unwrap_or_else(|| usage("--config requires a path"));
+                config = Some(Config::load(&PathBuf::from(path)));
+            }
+            "--data-dir" => {
+                let path = args.next().unwrap_or_else(|| usage("--data-dir requires a path"));
+                data_dir = Some(PathBuf::from(path));
+            }
+            "--new-identity" => new_identity = true,
+            arg if !arg.starts_with('-') && bind_addr.is_none() => match arg.parse::<SocketAddr>() {
+                Ok(addr) => bind_addr = Some(addr),
+                Err(err) => usage(&format!("invalid bind address {arg}: {err}")),
+            },
+            arg => usage(&format!("unexpected argument {arg}")),
+        }
+    }
+
+    let mut config = config.unwrap_or_default();
+    if let Some(bind_addr) = bind_addr {
+        config.set_bind_addr(bind_addr);
+    }
+    if let Some(data_dir) = data_dir {
+        config.set_data_dir(data_dir);
     }
 
-    let id = Id::generate();
-    println!("Running node with id={}", id);
-    let node = Node::new(id, listen_addr).expect("node should be able to start");
+    // the key being retired signs over to its successor, so peers carry its trust across
+    let retired = if new_identity {
+        keyfile::load(config.data_dir()).expect("node identity should be loadable")
+    } else {
+        None
+    };
+    let secret = keyfile::load_or_create(config.data_dir(), new_identity)
+        .expect("node identity should be loadable");
+    println!("Running node with id={}", secret.id());
+    let mut node = Node::new(secret.clone(), &config).expect("node should be able to start");
+    if let Some(retired) = retired {
+        println!("Announcing rotation from id={}", retired.id());
+        node.announce(KeyEvent::rotate(&retired, &secret));
+    }
 
     node.run();
 }
+
+/// Explains what was wrong with the arguments and how they should be given, then exits
+fn usage(problem: &str) -> ! {
+    eprintln!("{problem}\n{USAGE}");
+    exit(2)
+}
@@ -1,39 +1,113 @@
 use std::{
-    net::{SocketAddr, ToSocketAddrs},
-    time::{Duration, Instant},
+    collections::{BTreeMap, HashMap},
+    net::SocketAddr,
+    ops::Bound::{Excluded, Included, Unbounded},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-use ddb_lib::{Id, Message, Network};
+use ddb_lib::{
+    Clock, Entry, Id, KeyChange, KeyEvent, MemoryStore, Message, Network, REPLY_BUDGET, SecretId,
+    SequenceNumber, Signature, State, Store, SwapOutcome, Value, encoded_len, key_owner,
+};
 
-use crate::{data::Data, identification::Identification};
+use crate::{
+    config::{Config, Storage},
+    file_store::FileStore,
+    grants::Grants,
+    identification::Identification,
+    peer_cache::PeerCache,
+};
 
 static UPKEEP_INTERVAL: Duration = Duration::from_secs(15);
+/// How far a control message's send time may be from our clock before it is rejected
+static CONTROL_WINDOW: Duration = Duration::from_secs(60);
+/// How long tombstones are kept before compaction drops them, in milliseconds
+static TOMBSTONE_EXPIRY: u64 = 30 * 24 * 60 * 60 * 1000;
+/// Most keys or entries returned for one ListKeys, Scan, GetByAuthor or GetChanges request
+static MAX_PAGE: usize = 100;
+/// Number of cached peers contacted on startup
+static BOOTSTRAP_PEERS: usize = 10;
 
 pub struct Node {
     id: Id,
+    secret: SecretId,
     network: Network,
-    data: Data,
+    data: Box<dyn Store>,
+    /// Issues the sequence numbers of our entries
+    clock: Clock,
     identification: Identification,
+    /// Neighbors from this and previous runs
+    peers: PeerCache,
+    /// Delegations letting us write under other authors' namespaces
+    grants: Grants,
+    /// Ids allowed to send control messages, other than our own
+    operators: Vec<Id>,
+    /// Signatures of recently accepted control messages, so they cannot be replayed
+    recent_controls: HashMap<Signature, Instant>,
+    /// Changes to our own keys, passed on again at every upkeep so neighbors found later hear of them
+    announcements: Vec<KeyEvent>,
 }
 
 impl Node {
-    pub fn new<A: ToSocketAddrs>(id: Id, addrs: A) -> Option<Self> {
+    pub fn new(secret: SecretId, config: &Config) -> Option<Self> {
+        Self::with_store(secret, config, open_store(config))
+    }
+
+    /// Starts a node keeping its data in `data` rather than the store selected in the config
+    pub fn with_store(secret: SecretId, config: &Config, data: Box<dyn Store>) -> Option<Self> {
+        let id = secret.id();
+        let mut network = Network::new(config.bind_addr(), secret.clone())?;
+        network.set_read_timeout(Some(Duration::from_secs(1)));
+        network.set_difficulty(config.pow_difficulty());
+
+        // rejoin the network through the neighbors we had before
+        let peers = PeerCache::open(config.data_dir()).expect("peer cache should be loadable");
+        for addr in peers.sample(BOOTSTRAP_PEERS) {
+            network.request_verification(addr);
+        }
+
+        // never issue a sequence number older than one already stored
+        let mut clock = Clock::new();
+        for entry in data.entries() {
+            clock.observe(&entry.seq);
+        }
+
         Some(Self {
-            id: id.clone(),
-            network: Network::new(addrs, id.clone())?,
-            data: Data::new(),
-            identification: Identification::new(id),
+            id,
+            network,
+            secret,
+            data,
+            clock,
+            identification: Identification::open(id, config.data_dir())
+                .expect("trust should be loadable"),
+            peers,
+            grants: Grants::open(id, config.data_dir(), now()).expect("grants should be loadable"),
+            operators: config.operators().to_vec(),
+            recent_controls: HashMap::new(),
+            announcements: Vec::new(),
         })
     }
 
+    /// The store holding our data
+    pub fn store(&self) -> &dyn Store {
+        self.data.as_ref()
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.network.local_addr()
+    }
+
+    /// Tells the network about a change to one of our keys, such as a rotation to the key we run with now
+    pub fn announce(&mut self, event: KeyEvent) {
+        self.network
+            .send_several(Message::key_event(self.id, event.clone()));
+        self.announcements.push(event);
+    }
+
     pub fn run(mut self) {
-        self.network.set_read_timeout(Some(Duration::from_secs(1)));
         let mut last_upkeep = Instant::now();
         loop {
-            let msg = self.network.listen();
-            if let Some((from, msg)) = msg {
-                self.process_msg(from, msg)
-            }
+            self.step();
 
             if last_upkeep + UPKEEP_INTERVAL < Instant::now() {
                 last_upkeep = Instant::now();
@@ -42,35 +116,119 @@ impl Node {
         }
     }
 
+    /// Waits up to a second for the next message and processes it
+    pub fn step(&mut self) {
+        if let Some((from, msg)) = self.network.listen() {
+            self.process_msg(from, msg)
+        }
+    }
+
     fn process_msg(&mut self, from: SocketAddr, msg: Message) {
         println!("Got message {:?}", msg);
         // do some processing
-        let msg_id = msg.from().clone();
+        let msg_id = *msg.from();
         if self.identification.is_distrusted(&msg_id){
             return;
         }
+        // also ignore anything relayed through a distrusted peer
+        if let Some(peer_id) = self.network.peer_id(&from)
+            && self.identification.is_distrusted(peer_id)
+        {
+            return;
+        }
+        let is_signed = msg.is_signed();
+        let is_control = is_signed && self.authorise_control(&msg);
         match msg.take_msg_type() {
-            ddb_lib::MessageType::Verify(challenge, _padding) => {
+            ddb_lib::MessageType::Verify { challenge, stamp, key, addr } => {
                 // another node wants to contact us, reply with challenge
                 // if the challenge is in our list of challenges, do not reply
                 if !self.network.challenge_exists(&challenge) {
-                    self.network.verify(&from, challenge);
+                    self.network.verify(&from, msg_id, challenge, &stamp, &key, &addr);
                 }
             }
-            ddb_lib::MessageType::Verified(challenge, is_neighbor) => {
+            ddb_lib::MessageType::Verified { challenge, is_neighbor, stamp, key, observed, proof } => {
                 // our challenge has succeeded
-                self.network.verified(&challenge, is_neighbor);
+                if let Some(neighbor) = self.network.verified(msg_id, &challenge, is_neighbor, &stamp, &key, &observed, &proof) {
+                    self.peers.seen(neighbor);
+                }
             }
             ddb_lib::MessageType::Get { key, count } => {
-                let entries = self.data.get(&key, count);
+                let rank = |id: &Id| self.identification.rank(id);
+                let state = self.data.state(&key, &rank);
+                let mut entries = self.data.get(&key, count.min(MAX_PAGE), &rank);
+                fit(&mut entries, REPLY_BUDGET.saturating_sub(encoded_len(&state)));
+                self.network
+                    .send(from, Message::got(self.id, key, state, entries));
+            }
+            ddb_lib::MessageType::ListKeys { prefix, after, limit } => {
+                let start = match after.as_deref() {
+                    Some(after) if after >= prefix.as_str() => Excluded(after),
+                    _ => Included(prefix.as_str()),
+                };
+                let mut keys: Vec<_> = self
+                    .data
+                    .keys(start, Unbounded)
+                    .take_while(|key| key.starts_with(&prefix))
+                    .take(MAX_PAGE + 1)
+                    .map(str::to_string)
+                    .collect();
+                let next = paginate(&mut keys, limit, Clone::clone);
                 self.network
-                    .send(from, Message::values(self.id.clone(), entries));
+                    .send(from, Message::keys(self.id, keys, next));
             }
+            ddb_lib::MessageType::Keys { .. } => {} // we never list keys
+            ddb_lib::MessageType::Scan { start, end, after, limit } => {
+                let start = match after.as_deref() {
+                    Some(after) if after >= start.as_str() => Excluded(after),
+                    _ => Included(start.as_str()),
+                };
+                let end = end.as_deref().map_or(Unbounded, Excluded);
+                let rank = |id: &Id| self.identification.rank(id);
+                let mut entries: Vec<_> = self
+                    .data
+                    .keys(start, end)
+                    .take(MAX_PAGE + 1)
+                    // the winner among concurrent writes, as Get's state and Resolve pick it
+                    .flat_map(|key| self.data.resolve(key, &rank).0)
+                    .collect();
+                let next = paginate(&mut entries, limit, |entry| entry.key.clone());
+                self.network
+                    .send(from, Message::scanned(self.id, entries, next));
+            }
+            ddb_lib::MessageType::Scanned { .. } => {} // we never scan
+            ddb_lib::MessageType::GetByAuthor { author, after, limit } => {
+                let (entries, next) = self.data.by_author(&author, after, limit.clamp(1, MAX_PAGE));
+                self.network
+                    .send(from, Message::changes(self.id, entries, next));
+            }
+            ddb_lib::MessageType::GetChanges { key, after, limit } => {
+                let (entries, next) = self.data.changes(&key, after, limit.clamp(1, MAX_PAGE));
+                self.network
+                    .send(from, Message::changes(self.id, entries, next));
+            }
+            ddb_lib::MessageType::Changes { mut entries, .. } => {
+                // a peer catching us up, these are not new so they are not forwarded
+                self.retain_new(&mut entries);
+                entries.retain(|entry| self.identification.is_trusted(&entry.id));
+                self.data.ingest(entries);
+            }
+            ddb_lib::MessageType::GetHeads { key } => {
+                let mut entries = self.data.heads(&key);
+                fit(&mut entries, REPLY_BUDGET);
+                self.network
+                    .send(from, Message::heads(self.id, key, entries));
+            }
+            ddb_lib::MessageType::Got { .. } => {} // we never send Get
+            ddb_lib::MessageType::Heads { .. } => {} // we never ask for heads
+            ddb_lib::MessageType::Resolve { key } => {
+                let (winner, mut alternatives) = self.data.resolve(&key, &|id| self.identification.rank(id));
+                fit(&mut alternatives, REPLY_BUDGET.saturating_sub(encoded_len(&winner)));
+                self.network
+                    .send(from, Message::resolved(self.id, key, winner, alternatives));
+            }
+            ddb_lib::MessageType::Resolved { .. } => {} // we never ask for resolved values
             ddb_lib::MessageType::Values(mut entries) => {
-                // discard duplicates and distrusted messages
-                entries.retain(|entry| {
-                    !self.identification.is_distrusted(&entry.id) && !self.data.contains(entry)
-                });
+                self.retain_new(&mut entries);
 
                 // if all the messages are filtered out, no need to continue
                 if entries.is_empty() {
@@ -86,23 +244,54 @@ impl Node {
                 // store trusted messages
                 self.data.ingest(entries);
             }
-            ddb_lib::MessageType::Set(mut entry) => {
-                if self.identification.is_us(&msg_id) {
-                    // calculate proper next id
-                    let next_seq = self.data.get_next_id(&entry.key);
-                    entry.seq = next_seq;
-                    self.data.insert(entry.clone());
-
-                    // rebroadcast
-                    self.network
-                        .send_several(Message::values(self.id, vec![entry]));
+            ddb_lib::MessageType::Set { key, val, ttl } => {
+                if is_control {
+                    self.write(key, val, ttl);
                 }
             }
-            ddb_lib::MessageType::Link(addr) => {
-                if self.identification.is_us(&msg_id) {
-                    if let Ok(addr) = addr.parse() {
-                        self.network.request_verification(self.id, addr);
+            ddb_lib::MessageType::CompareAndSet { key, expected, val } => {
+                // always answered, so a client is not left waiting
+                let outcome = if is_control {
+                    let heads: Vec<_> = self.data.heads(&key).iter().map(Entry::hash).collect();
+                    let unchanged = match expected {
+                        Some(expected) => heads == [expected],
+                        None => heads.is_empty(),
+                    };
+                    if !unchanged {
+                        SwapOutcome::Changed
+                    } else if self.write(key.clone(), val, None).is_some() {
+                        SwapOutcome::Written
+                    } else {
+                        SwapOutcome::Refused
                     }
+                } else {
+                    SwapOutcome::Refused
+                };
+                let mut heads = self.data.heads(&key);
+                fit(&mut heads, REPLY_BUDGET);
+                self.network
+                    .send(from, Message::swapped(self.id, key, outcome, heads));
+            }
+            ddb_lib::MessageType::Swapped { .. } => {} // we never compare and set
+            ddb_lib::MessageType::Batch { writes } => {
+                if is_control {
+                    self.write_batch(writes);
+                }
+            }
+            ddb_lib::MessageType::Delete { key } => {
+                if is_control {
+                    self.write(key, Value::Tombstone, None);
+                }
+            }
+            ddb_lib::MessageType::Grant(chain) => {
+                // grants carry their own signatures, so they are taken from anyone
+                if !self.grants.add(chain, now()) {
+                    eprintln!("ignored a grant that does not let us write anything, or one too many");
+                }
+            }
+            ddb_lib::MessageType::Link(addr) => {
+                if is_control && let Ok(addr) = addr.parse() {
+                    self.network.request_verification(addr);
                 }
             }
             ddb_lib::MessageType::Neighbors(mut neighbors) => {
@@ -115,31 +304,228 @@ impl Node {
             ddb_lib::MessageType::GetTrust => {
                 // get all trust levels, return them
                 for (id, level) in self.identification.base_trust() {
-                    self.network.send_addr(from, Message::trust(self.id, *id, (*level*10_000.0)as i16));
+                    let msg = Message::trust_level(self.id, *id, (*level*10_000.0)as i16).signed(&self.secret);
+                    self.network.send_addr(from, msg);
                 }
             }
             ddb_lib::MessageType::Trust{of, delta: amount} => {
-                if self.identification.is_us(&msg_id) {
+                if is_control {
                     self.identification.change_trust(of, amount as f32 / 10000.0);
-                }else{
-                    // entries to be used for the trust offset
-                    self.identification.adjust_offset(msg_id, of, amount as f32 / 10000.0);
                 }
             }
+            ddb_lib::MessageType::TrustLevel { of, level } => {
+                // another node's opinion, used for the trust offset. We and our operators
+                // change trust directly with Trust, so those are never taken as offsets
+                if is_signed && !self.identification.is_us(&msg_id) && !self.operators.contains(&msg_id) {
+                    self.identification.adjust_offset(msg_id, of, level as f32 / 10000.0);
+                }
+            }
+            ddb_lib::MessageType::KeyEvent(event) => {
+                if !event.verify() || !self.identification.apply_key_event(&event) {
+                    return;
+                }
+                match &event.change {
+                    KeyChange::Revoke => {
+                        self.data.revoke(event.id);
+                        self.operators.retain(|operator| *operator != event.id);
+                    }
+                    KeyChange::Rotate { successor, .. } => {
+                        // operators keep their rights under their new key
+                        for operator in self.operators.iter_mut() {
+                            if *operator == event.id {
+                                *operator = *successor;
+                            }
+                        }
+                    }
+                }
+
+                // pass the news along
+                self.network
+                    .send_several(Message::key_event(self.id, event));
+            }
         };
     }
 
+    /// Discards forged, oversized, duplicate, distrusted, expired, compacted and future dated entries, and writes to others' namespaces
+    fn retain_new(&mut self, entries: &mut Vec<Entry>) {
+        let now = now();
+        entries.retain(|entry| entry.verify() && entry.fits() && entry.may_write(now) && !entry.is_expired(now));
+        // a batch missing an entry, or with one discarded above, is dropped whole
+        Entry::retain_complete_batches(entries);
+        entries.retain(|entry| {
+            !self.identification.is_distrusted(&entry.id)
+                && !self.data.contains(entry)
+                && !self.data.is_compacted(entry)
+                && self.clock.observe(&entry.seq)
+        });
+    }
+
+    /// Writes a new entry of our own, superseding the current heads of its key.
+    ///
+    /// Returns the entry, or None if we may not write to the key. With a `ttl`, in milliseconds,
+    /// the entry expires that long after it is written.
+    fn write(&mut self, key: String, val: Value, ttl: Option<u64>) -> Option<Entry> {
+        // later than every entry we have seen, for this key or any other
+        let seq = self.clock.tick();
+        let expires = ttl.map(|ttl| seq.time.saturating_add(ttl));
+        let entry = self.stamp(seq, key, val, 0, expires)?;
+        self.data.insert(entry.clone());
+
+        // rebroadcast
+        self.network
+            .send_several(Message::values(self.id, vec![entry.clone()]));
+        Some(entry)
+    }
+
+    /// Writes several keys as one batch, or nothing if we may not write to one of them
+    fn write_batch(&mut self, writes: Vec<(String, Value)>) {
+        // the last value given for a key wins
+        let writes: BTreeMap<_, _> = writes.into_iter().collect();
+        if writes.is_empty() {
+            return;
+        }
+        let seq = self.clock.tick();
+        let batch = writes.len() as u32;
+        let Some(entries) = writes
+            .into_iter()
+            .map(|(key, val)| self.stamp(seq, key, val, batch, None))
+            .collect::<Option<Vec<_>>>()
+        else {
+            return;
+        };
+        // a batch that cannot be sent in one message could never be applied by anyone else
+        if encoded_len(&entries) > REPLY_BUDGET {
+            return;
+        }
+        self.data.ingest(entries.clone());
+
+        // sent as one message, so the batch travels as a unit
+        self.network
+            .send_several(Message::values(self.id, entries));
+    }
+
+    /// Makes an entry of ours superseding the key's heads, unless we may not write to the key or it is too large
+    fn stamp(&self, seq: SequenceNumber, key: String, mut val: Value, batch: u32, expires: Option<u64>) -> Option<Entry> {
+        // another author's namespace, nobody would accept the entry unless we were granted writes
+        let mut capabilities = Vec::new();
+        if key_owner(&key).is_some_and(|owner| owner != self.id) {
+            capabilities = self.grants.chain_for(&key, seq.time)?;
+        }
+
+        // a remove only takes out the adds we have seen
+        if let Value::SetRemove { elem, observed } = &mut val {
+            let entries = self.data.get(&key, usize::MAX, &|id| self.identification.rank(id));
+            *observed = State::observed_adds(&entries, elem);
+        }
+
+        let parents = self.data.heads(&key).iter().map(Entry::hash).collect();
+        let mut entry = Entry::with_parents(&self.secret, seq, key, val, parents);
+        if batch > 0 || expires.is_some() {
+            entry.batch = batch;
+            entry.expires = expires;
+            entry.sign(&self.secret);
+        }
+        Some(entry.with_capabilities(capabilities)).filter(Entry::fits)
+    }
+
+    /// Checks that a signed message comes from us or an operator, and is fresh.
+    ///
+    /// Accepted messages are remembered so the same message cannot be replayed.
+    fn authorise_control(&mut self, msg: &Message) -> bool {
+        let from = msg.from();
+        if !self.identification.is_us(from) && !self.operators.contains(from) {
+            return false;
+        }
+
+        let (Some(sent), Some(sig)) = (msg.sent(), msg.signature()) else {
+            return false;
+        };
+        if now().abs_diff(sent) > CONTROL_WINDOW.as_millis() as u64 {
+            return false;
+        }
+
+        self.recent_controls.insert(*sig, Instant::now()).is_none()
+    }
+
     /// Periodic functions to maintain the health of the network
     fn upkeep(&mut self) {
         println!("upkeep!");
 
+        // forget control messages that are too old to be accepted anyway
+        self.recent_controls
+            .retain(|_, received| *received + 2 * CONTROL_WINDOW > Instant::now());
+
         // let network clean up its old items
         self.network.clean();
 
+        // drop deleted values, and tombstones that have expired
+        self.data.compact(now().saturating_sub(TOMBSTONE_EXPIRY));
+
+        // sweep away entries that have outlived their ttl
+        self.data.expire(now());
+
+        // let the store tidy up, such as snapshotting its log
+        self.data.maintain();
+
+        // save the trust levels peers have reported since the last upkeep
+        self.identification.flush();
+
+        // and the neighbors verified since then
+        self.peers.flush();
+
         // prepare a list of neighbors to send
         self.network.swap_neighbors();
 
+        // keep announcing our key changes, for neighbors that missed them
+        for event in self.announcements.clone() {
+            self.network
+                .send_several(Message::key_event(self.id, event));
+        }
+
         // Request some trust levels
         self.network.send_n(Message::get_trust(self.id), 1);
     }
 }
+
+/// Milliseconds since the unix epoch, the time used by sequence numbers
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_millis() as u64)
+}
+
+/// Cuts a page of results down to `limit`, at most `MAX_PAGE`, and to what fits in a reply.
+///
+/// Returns the cursor of the last item kept if any were cut off.
+fn paginate<T: serde::Serialize>(items: &mut Vec<T>, limit: usize, cursor: impl Fn(&T) -> String) -> Option<String> {
+    let len = items.len();
+    items.truncate(limit.clamp(1, MAX_PAGE));
+    fit(items, REPLY_BUDGET);
+    if items.len() == len {
+        return None;
+    }
+    items.last().map(cursor)
+}
+
+/// Cuts items down to those that fit in `budget` bytes once encoded, always keeping the first
+fn fit<T: serde::Serialize>(items: &mut Vec<T>, budget: usize) {
+    let mut size = 0;
+    let fitting = items
+        .iter()
+        .take_while(|item| {
+            size += encoded_len(item);
+            size <= budget
+        })
+        .count();
+    items.truncate(fitting.max(1));
+}
+
+/// Opens the store selected in the config
+fn open_store(config: &Config) -> Box<dyn Store> {
+    match config.storage() {
+        Storage::Memory => Box::new(MemoryStore::new()),
+        Storage::File => {
+            Box::new(FileStore::open(config.data_dir()).expect("data should be loadable"))
+        }
+    }
+}
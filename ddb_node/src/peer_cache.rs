@@ -0,0 +1,123 @@
+use std::{
+    collections::HashMap,
+    io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use rand::{rng, seq::IndexedRandom};
+
+use crate::saved;
+
+/// Name of the peer cache within the data directory
+static PEERS_FILE: &str = "peers.json";
+static PEERS_VERSION: u32 = 1;
+/// Peers not seen for this long are forgotten
+static PEER_EXPIRY: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+/// Most peers kept, beyond this the least likely to be sampled are forgotten
+pub static MAX_PEERS: usize = 1000;
+
+/// What we know about a neighbor from previous runs
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub struct PeerRecord {
+    /// When the peer was last verified, in seconds since the unix epoch
+    pub last_seen: u64,
+    /// Number of times the peer has been verified as a neighbor
+    pub successes: u32,
+}
+
+/// The peer cache as saved to disk
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+struct PeersFile {
+    peers: HashMap<SocketAddr, PeerRecord>,
+}
+
+/// Neighbors verified recently, kept so a restarted node can find the network again
+pub struct PeerCache {
+    path: PathBuf,
+    peers: HashMap<SocketAddr, PeerRecord>,
+    /// Whether there are changes waiting for `flush`
+    unsaved: bool,
+}
+
+impl PeerCache {
+    /// Loads the peer cache saved in `dir`, forgetting peers that have not been seen for a while
+    pub fn open(dir: &Path) -> io::Result<Self> {
+        let path = dir.join(PEERS_FILE);
+        let file: PeersFile = saved::load(&path, PEERS_VERSION)?;
+
+        let mut peers = file.peers;
+        let cutoff = now().saturating_sub(PEER_EXPIRY.as_secs());
+        peers.retain(|_, record| record.last_seen >= cutoff);
+        Ok(Self {
+            path,
+            peers,
+            unsaved: false,
+        })
+    }
+
+    /// Records that `addr` has been verified as a neighbor, saved at the next `flush`
+    pub fn seen(&mut self, addr: SocketAddr) {
+        let now = now();
+        let record = self.peers.entry(addr).or_insert(PeerRecord {
+            last_seen: 0,
+            successes: 0,
+        });
+        record.last_seen = now;
+        record.successes += 1;
+        self.unsaved = true;
+
+        if self.peers.len() > MAX_PEERS
+            && let Some(weakest) = self
+                .peers
+                .iter()
+                .filter(|(peer, _record)| **peer != addr)
+                .min_by(|(_, a), (_, b)| weight(a, now).total_cmp(&weight(b, now)))
+                .map(|(peer, _record)| *peer)
+        {
+            self.peers.remove(&weakest);
+        }
+    }
+
+    /// Saves the peers seen since the last save
+    pub fn flush(&mut self) {
+        if self.unsaved {
+            self.save();
+        }
+    }
+
+    /// Up to `n` peers worth contacting, picked at random so restarted nodes do not all reach for the same ones.
+    ///
+    /// Peers verified more often, and more recently, are more likely to be picked.
+    pub fn sample(&self, n: usize) -> Vec<SocketAddr> {
+        let now = now();
+        let peers: Vec<_> = self.peers.iter().collect();
+        peers
+            .choose_multiple_weighted(&mut rng(), n, |(_addr, record)| weight(record, now))
+            .map_or_else(|_| Vec::new(), |chosen| chosen.map(|(addr, _record)| **addr).collect())
+    }
+
+    /// Saves the cache in place of the last save
+    fn save(&mut self) {
+        self.unsaved = false;
+        let file = PeersFile {
+            peers: self.peers.clone(),
+        };
+        if let Err(err) = saved::save(&self.path, PEERS_VERSION, &file) {
+            eprintln!("failed to save peer cache: {err}");
+        }
+    }
+}
+
+/// How likely a peer is to be picked by `sample`, its successes fading by half for each day it has not been seen
+fn weight(record: &PeerRecord, now: u64) -> f64 {
+    let days = now.saturating_sub(record.last_seen) as f64 / (24.0 * 60.0 * 60.0);
+    f64::from(record.successes.max(1)) / 2f64.powf(days)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
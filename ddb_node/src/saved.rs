@@ -0,0 +1,56 @@
+use std::{
+    fs::{self, File},
+    io::{self, ErrorKind},
+    path::Path,
+};
+
+use serde::{Serialize, de::DeserializeOwned};
+
+/// The version a file was saved with, read before the rest of it
+#[derive(serde::Deserialize)]
+struct Version {
+    #[serde(default)]
+    version: u32,
+}
+
+/// A value as saved, with the version of its format alongside its fields
+#[derive(serde::Serialize)]
+struct Versioned<'a, T> {
+    version: u32,
+    #[serde(flatten)]
+    data: &'a T,
+}
+
+/// Loads json saved by `save`, or the default if nothing was saved yet.
+///
+/// Files saved in a format newer than `version` are refused rather than misread.
+pub fn load<T: DeserializeOwned + Default>(path: &Path, version: u32) -> io::Result<T> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(T::default()),
+        Err(err) => return Err(err),
+    };
+    let saved = serde_json::from_slice::<Version>(&data)
+        .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))?;
+    if saved.version > version {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("{} has version {}, newer than this node supports", path.display(), saved.version),
+        ));
+    }
+    serde_json::from_slice(&data).map_err(|err| io::Error::new(ErrorKind::InvalidData, err))
+}
+
+/// Saves `data` as json with its `version`, to a temporary file renamed into place so a crash
+/// leaves either the old or the new file whole.
+///
+/// `data` is saved as an object holding its fields and the version, so it must be a struct.
+pub fn save<T: Serialize>(path: &Path, version: u32, data: &T) -> io::Result<()> {
+    let mut tmp_name = path.as_os_str().to_owned();
+    tmp_name.push(".tmp");
+    let tmp_path = Path::new(&tmp_name);
+    let mut tmp = File::create(tmp_path)?;
+    serde_json::to_writer(&mut tmp, &Versioned { version, data }).map_err(io::Error::other)?;
+    tmp.sync_all()?;
+    fs::rename(tmp_path, path)
+}
@@ -0,0 +1,34 @@
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use ddb_node::{Config, MAX_POW_DIFFICULTY};
+
+    /// A config file of its own for each test
+    fn config_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ddb_config_{name}_{}.toml", std::process::id()))
+    }
+
+    #[test]
+    fn difficulties_in_range_are_loaded() {
+        let path = config_path("in_range");
+        fs::write(&path, format!("pow_difficulty = {MAX_POW_DIFFICULTY}\n")).unwrap();
+        assert_eq!(Config::load(&path).pow_difficulty(), MAX_POW_DIFFICULTY);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    #[should_panic(expected = "pow_difficulty should be at most")]
+    fn difficulties_that_could_never_be_met_are_refused() {
+        let path = config_path("out_of_range");
+        fs::write(&path, "pow_difficulty = 300\n").unwrap();
+        Config::load(&path);
+    }
+
+    #[test]
+    fn set_difficulties_are_clamped() {
+        let mut config = Config::default();
+        config.set_pow_difficulty(u32::MAX);
+        assert_eq!(config.pow_difficulty(), MAX_POW_DIFFICULTY);
+    }
+}
@@ -0,0 +1,97 @@
+#[cfg(test)]
+mod tests {
+    use std::{fs, ops::Bound::Unbounded, path::PathBuf};
+
+    use ddb_lib::{Clock, Entry, SecretId, Store, Value};
+    use ddb_node::FileStore;
+
+    /// An empty directory of its own for each test
+    fn data_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ddb_file_store_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn batch(secret: &SecretId, clock: &mut Clock, keys: &[&str]) -> Vec<Entry> {
+        let seq = clock.tick();
+        keys.iter()
+            .map(|key| {
+                let mut entry = Entry::new(secret, seq, key.to_string(), "val".into());
+                entry.batch = keys.len() as u32;
+                entry.sign(secret);
+                entry
+            })
+            .collect()
+    }
+
+    #[test]
+    fn inserts_ingests_and_revocations_survive_a_restart() {
+        let dir = data_dir("replay");
+        let (a, b) = (SecretId::generate(), SecretId::generate());
+        let mut clock = Clock::new();
+        let single = Entry::new(&a, clock.tick(), "single".into(), "val".into());
+        let batch = batch(&a, &mut clock, &["x", "y"]);
+        let revoked = Entry::new(&b, clock.tick(), "single".into(), "newer".into());
+
+        let mut store = FileStore::open(&dir).unwrap();
+        store.insert(single.clone());
+        store.ingest(batch.clone());
+        store.insert(revoked.clone());
+        store.revoke(b.id());
+        drop(store);
+
+        let store = FileStore::open(&dir).unwrap();
+        assert_eq!(store.get("single", 10, &|_| 0.5), vec![single]);
+        assert_eq!(store.get("y", 1, &|_| 0.5), batch[1..]);
+        assert!(store.is_revoked(&b.id()));
+        assert!(store.contains(&revoked));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn sweeps_are_replayed() {
+        let dir = data_dir("expire");
+        let secret = SecretId::generate();
+        let mut clock = Clock::new();
+        let lasting = Entry::new(&secret, clock.tick(), "key".into(), "lasting".into());
+        let mut ephemeral = Entry::new(&secret, clock.tick(), "key".into(), "ephemeral".into());
+        ephemeral.expires = Some(ephemeral.seq.time + 60_000);
+        ephemeral.sign(&secret);
+
+        let mut store = FileStore::open(&dir).unwrap();
+        store.insert(lasting.clone());
+        store.insert(ephemeral.clone());
+        assert_eq!(store.expire(ephemeral.seq.time + 60_000), 1);
+        drop(store);
+
+        let store = FileStore::open(&dir).unwrap();
+        assert!(!store.contains(&ephemeral));
+        assert_eq!(store.entries().cloned().collect::<Vec<_>>(), vec![lasting]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn compaction_is_remembered_across_a_restart() {
+        let dir = data_dir("compact");
+        let secret = SecretId::generate();
+        let mut clock = Clock::new();
+        let hidden = Entry::new(&secret, clock.tick(), "key".into(), "val".into());
+        let tombstone = Entry::new(&secret, clock.tick(), "key".into(), Value::Tombstone);
+        let after = Entry::new(&secret, clock.tick(), "other".into(), "val".into());
+
+        let mut store = FileStore::open(&dir).unwrap();
+        store.insert(hidden.clone());
+        store.insert(tombstone.clone());
+        assert_eq!(store.compact(u64::MAX), 2);
+        // logged after the snapshot compaction took
+        store.insert(after.clone());
+        drop(store);
+
+        let store = FileStore::open(&dir).unwrap();
+        assert!(store.is_compacted(&hidden));
+        assert!(store.is_compacted(&tombstone));
+        assert_eq!(store.keys(Unbounded, Unbounded).collect::<Vec<_>>(), vec!["other"]);
+        assert_eq!(store.entries().cloned().collect::<Vec<_>>(), vec![after]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
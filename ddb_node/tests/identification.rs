@@ -0,0 +1,34 @@
+#[cfg(test)]
+mod tests {
+    use ddb_lib::{KeyEvent, SecretId};
+    use ddb_node::Identification;
+
+    fn assert_near(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-6, "{a} != {b}");
+    }
+
+    #[test]
+    fn rotations_keep_the_lower_trust_and_the_successors_own_reports() {
+        let (old, new) = (SecretId::generate(), SecretId::generate());
+        let (peer, other) = (SecretId::generate().id(), SecretId::generate().id());
+        let mut identification = Identification::new(SecretId::generate().id());
+        identification.change_trust(old.id(), 0.4);
+        identification.change_trust(new.id(), -0.3);
+        identification.adjust_offset(peer, old.id(), 1.0);
+        identification.adjust_offset(old.id(), new.id(), 1.0);
+        identification.adjust_offset(old.id(), other, 1.0);
+        identification.adjust_offset(new.id(), other, 0.1);
+
+        assert!(identification.apply_key_event(&KeyEvent::rotate(&old, &new)));
+        assert_eq!(identification.current_key(old.id()), new.id());
+        // rotating into a distrusted key does not launder it
+        assert_near(identification.get_trust(&new.id()) - identification.get_offset(&new.id()), 0.2);
+        // the peer's report carries over, the old key vouching for its successor does not
+        assert_near(identification.get_offset(&new.id()), 0.25);
+        // the successor's own report about others stands
+        assert_near(identification.get_offset(&other), 0.2 * (0.1 - 0.5));
+
+        // only the first rotation counts
+        assert!(!identification.apply_key_event(&KeyEvent::rotate(&old, &SecretId::generate())));
+    }
+}
@@ -0,0 +1,81 @@
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{self, OpenOptions},
+        io::Write,
+        path::PathBuf,
+    };
+
+    use ddb_lib::{Clock, Entry, SecretId, SequenceNumber};
+    use ddb_node::{Journal, Record};
+
+    /// An empty directory of its own for each test
+    fn data_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ddb_journal_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn torn_lines_are_discarded() {
+        let dir = data_dir("torn");
+        let secret = SecretId::generate();
+        let entry = Entry::new(&secret, Clock::new().tick(), "key".into(), "val".into());
+        let (mut journal, _, _) = Journal::open(&dir).unwrap();
+        journal.append(&Record::Insert(Box::new(entry.clone()))).unwrap();
+        drop(journal);
+
+        // a crash halfway through writing the next record
+        let log = dir.join("data.log");
+        let len = fs::metadata(&log).unwrap().len();
+        OpenOptions::new().append(true).open(&log).unwrap().write_all(b"{\"Revoke\":").unwrap();
+
+        let (mut journal, _, records) = Journal::open(&dir).unwrap();
+        assert!(matches!(&records[..], [Record::Insert(inserted)] if **inserted == entry));
+        assert_eq!(fs::metadata(&log).unwrap().len(), len);
+
+        // records appended afterwards are not lost behind the torn line
+        journal.append(&Record::Revoke(secret.id())).unwrap();
+        drop(journal);
+        let (_, _, records) = Journal::open(&dir).unwrap();
+        assert!(matches!(&records[..], [Record::Insert(_), Record::Revoke(id)] if *id == secret.id()));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn only_records_after_the_snapshot_are_replayed() {
+        let dir = data_dir("snapshot");
+        let secret = SecretId::generate();
+        let mut clock = Clock::new();
+        let old = Entry::new(&secret, clock.tick(), "old".into(), "val".into());
+        let new = Entry::new(&secret, clock.tick(), "new".into(), "val".into());
+        let compacted = ("gone".to_string(), SequenceNumber { time: 5, counter: 0 });
+
+        let (mut journal, _, _) = Journal::open(&dir).unwrap();
+        journal.append(&Record::Insert(Box::new(old.clone()))).unwrap();
+        journal
+            .snapshot(vec![old.clone()], vec![secret.id()], vec![compacted.clone()])
+            .unwrap();
+        assert_eq!(journal.records(), 0);
+        journal.append(&Record::Ingest(vec![new.clone()])).unwrap();
+        journal.append(&Record::Expire(1)).unwrap();
+        drop(journal);
+
+        let (journal, snapshot, records) = Journal::open(&dir).unwrap();
+        assert_eq!(snapshot.entries, vec![old]);
+        assert_eq!(snapshot.revoked, vec![secret.id()]);
+        assert_eq!(snapshot.compacted, vec![compacted]);
+        assert!(matches!(&records[..], [Record::Ingest(entries), Record::Expire(1)] if *entries == vec![new.clone()]));
+        assert_eq!(journal.records(), 2);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn newer_snapshots_are_refused() {
+        let dir = data_dir("version");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("data.snapshot"), r#"{"version":99,"entries":[],"revoked":[]}"#).unwrap();
+        assert!(Journal::open(&dir).is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,64 @@
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use ddb_node::keyfile::{self, KEY_FILE};
+
+    /// An empty directory of its own for each test
+    fn data_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ddb_keyfile_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn identities_are_created_once_and_loaded_after() {
+        let dir = data_dir("load");
+        assert!(keyfile::load(&dir).unwrap().is_none());
+        let created = keyfile::load_or_create(&dir, false).unwrap();
+        let loaded = keyfile::load_or_create(&dir, false).unwrap();
+        assert_eq!(created.id(), loaded.id());
+        assert_eq!(keyfile::load(&dir).unwrap().unwrap().id(), created.id());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn new_identities_keep_every_retired_key() {
+        let dir = data_dir("retire");
+        let first = keyfile::load_or_create(&dir, false).unwrap();
+        let second = keyfile::load_or_create(&dir, true).unwrap();
+        let third = keyfile::load_or_create(&dir, true).unwrap();
+        assert_ne!(first.id(), second.id());
+        assert_ne!(second.id(), third.id());
+
+        let retired = |name: &str| fs::read_to_string(dir.join(name)).unwrap().trim().to_string();
+        assert_eq!(retired("node.key.old"), first.to_hex());
+        assert_eq!(retired("node.key.old.1"), second.to_hex());
+        assert_eq!(keyfile::load_or_create(&dir, false).unwrap().id(), third.id());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn key_files_others_can_read_are_refused() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = data_dir("permissions");
+        keyfile::load_or_create(&dir, false).unwrap();
+        let path = dir.join(KEY_FILE);
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        assert!(keyfile::load_or_create(&dir, false).is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn corrupt_key_files_are_refused() {
+        let dir = data_dir("corrupt");
+        keyfile::load_or_create(&dir, false).unwrap();
+        fs::write(dir.join(KEY_FILE), "not a key\n").unwrap();
+        assert!(keyfile::load_or_create(&dir, false).is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,336 @@
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        ops::Bound::Unbounded,
+        net::SocketAddr,
+        path::{Path, PathBuf},
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    use ddb_lib::{
+        Clock, Entry, KeyEvent, MemoryStore, Message, MessageType, Network, SecretId, Store,
+        SwapOutcome,
+    };
+    use ddb_node::{Config, Node};
+
+    /// An empty directory of its own for each test
+    fn data_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ddb_node_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// A config for a node on a free localhost port, keeping its state in `dir`
+    fn config(dir: &Path) -> Config {
+        let mut config = Config::default();
+        config.set_bind_addr(SocketAddr::from(([127, 0, 0, 1], 0)));
+        config.set_data_dir(dir.to_path_buf());
+        config.set_pow_difficulty(0);
+        config
+    }
+
+    /// A node keeping its data in memory
+    fn node(dir: &Path) -> Node {
+        Node::with_store(SecretId::generate(), &config(dir), Box::new(MemoryStore::new())).unwrap()
+    }
+
+    /// A node keeping its data in memory, taking control messages from `operator`
+    fn operated_node(dir: &Path, operator: &SecretId) -> Node {
+        let mut config = config(dir);
+        config.set_operators(vec![operator.id()]);
+        Node::with_store(SecretId::generate(), &config, Box::new(MemoryStore::new())).unwrap()
+    }
+
+    /// Milliseconds since the unix epoch
+    fn now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64
+    }
+
+    /// A peer that has verified the node and been verified by it in turn, so they are neighbors
+    fn connect(node: &mut Node, secret: SecretId) -> Network {
+        let id = secret.id();
+        let mut peer = Network::new("127.0.0.1:0", secret).unwrap();
+        peer.set_read_timeout(Some(Duration::from_secs(5)));
+        let addr = node.local_addr().unwrap();
+        peer.request_verification(addr);
+        node.step();
+        assert!(recv(&mut peer, node).is_none());
+
+        // offering no neighbors makes the node verify the peer itself
+        assert!(peer.send(addr, Message::neighbors(id, Vec::new())));
+        node.step();
+        let (from, msg) = peer.listen().unwrap();
+        let MessageType::Verify { challenge, stamp, key, addr: to } = msg.msg_type() else {
+            panic!("the node should verify the peer")
+        };
+        peer.verify(&from, *msg.from(), challenge.clone(), stamp, key, to);
+        node.step();
+        peer
+    }
+
+    /// The next message the peer receives, answering the node's handshake messages along the way.
+    ///
+    /// Returns None once the peer has verified the node.
+    fn recv(peer: &mut Network, node: &mut Node) -> Option<Message> {
+        loop {
+            let (from, msg) = peer.listen().expect("the node should send something");
+            match msg.msg_type() {
+                MessageType::Verify { challenge, stamp, key, addr } => {
+                    peer.verify(&from, *msg.from(), challenge.clone(), stamp, key, addr);
+                    node.step();
+                }
+                MessageType::Verified { challenge, is_neighbor, stamp, key, observed, proof } => {
+                    peer.verified(*msg.from(), challenge, *is_neighbor, stamp, key, observed, proof)?;
+                    return None;
+                }
+                _ => return Some(msg),
+            }
+        }
+    }
+
+    /// Sends a compare and set, returning the outcome and heads the node replies with
+    fn compare_and_set(peer: &mut Network, node: &mut Node, msg: Message) -> (SwapOutcome, Vec<Entry>) {
+        peer.send(node.local_addr().unwrap(), msg);
+        node.step();
+        loop {
+            // skipping the gossip of what was written
+            if let MessageType::Swapped { outcome, heads, .. } = recv(peer, node).unwrap().take_msg_type() {
+                return (outcome, heads);
+            }
+        }
+    }
+
+    #[test]
+    fn nodes_keep_the_store_they_are_given() {
+        let dir = data_dir("with_store");
+        let secret = SecretId::generate();
+        let entry = Entry::new(&secret, Clock::new().tick(), "key".into(), "val".into());
+        let mut store = MemoryStore::new();
+        store.insert(entry.clone());
+
+        let node = Node::with_store(secret, &config(&dir), Box::new(store)).unwrap();
+        assert!(node.store().contains(&entry));
+        assert_ne!(node.local_addr().unwrap().port(), 0);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn key_events_are_passed_on_to_neighbors() {
+        let dir = data_dir("key_events");
+        let mut node = node(&dir);
+        let sender_secret = SecretId::generate();
+        let sender_id = sender_secret.id();
+        let mut sender = connect(&mut node, sender_secret);
+        let mut neighbor = connect(&mut node, SecretId::generate());
+
+        let (old, new) = (SecretId::generate(), SecretId::generate());
+        let rotation = KeyEvent::rotate(&old, &new);
+        sender.send(node.local_addr().unwrap(), Message::key_event(sender_id, rotation.clone()));
+        node.step();
+        let msg = recv(&mut neighbor, &mut node).unwrap();
+        assert_eq!(msg.msg_type(), &MessageType::KeyEvent(rotation));
+
+        // and a node announces the rotation to the key it now runs with
+        let rotation = KeyEvent::rotate(&SecretId::generate(), &SecretId::generate());
+        node.announce(rotation.clone());
+        let msg = recv(&mut neighbor, &mut node).unwrap();
+        assert_eq!(msg.msg_type(), &MessageType::KeyEvent(rotation));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn control_messages_must_be_signed_by_an_operator() {
+        let dir = data_dir("control_signers");
+        let operator = SecretId::generate();
+        let mut node = operated_node(&dir, &operator);
+        let addr = node.local_addr().unwrap();
+        let mut controller = connect(&mut node, operator.clone());
+        let stranger = SecretId::generate();
+        let mut other = connect(&mut node, stranger.clone());
+
+        // unsigned
+        controller.send(addr, Message::set(operator.id(), "unsigned".into(), "val".into()));
+        node.step();
+        // signed, but not by an operator
+        other.send(addr, Message::set(stranger.id(), "stranger".into(), "val".into()).signed(&stranger));
+        node.step();
+        other.send(addr, Message::batch(stranger.id(), vec![("stranger".into(), "val".into())]).signed(&stranger));
+        node.step();
+        assert_eq!(node.store().entries().count(), 0);
+
+        controller.send(addr, Message::set(operator.id(), "signed".into(), "val".into()).signed(&operator));
+        node.step();
+        assert_eq!(node.store().keys(Unbounded, Unbounded).collect::<Vec<_>>(), vec!["signed"]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn control_messages_must_be_fresh_and_are_taken_once() {
+        let dir = data_dir("control_freshness");
+        let operator = SecretId::generate();
+        let mut node = operated_node(&dir, &operator);
+        let addr = node.local_addr().unwrap();
+        let mut controller = connect(&mut node, operator.clone());
+
+        // signed more than a minute ago, or from more than a minute ahead
+        for sent in [now() - 2 * 60 * 1000, now() + 2 * 60 * 1000] {
+            let msg = Message::set(operator.id(), "stale".into(), "val".into()).signed_at(&operator, sent);
+            controller.send(addr, msg);
+            node.step();
+        }
+        assert_eq!(node.store().entries().count(), 0);
+
+        // the same message again is a replay, even sealed anew
+        let msg = Message::set(operator.id(), "key".into(), "val".into()).signed(&operator);
+        controller.send(addr, msg.clone());
+        node.step();
+        controller.send(addr, msg);
+        node.step();
+        assert_eq!(node.store().entries().count(), 1);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn compare_and_set_writes_only_over_the_expected_head() {
+        let dir = data_dir("compare_and_set");
+        let operator = SecretId::generate();
+        let mut node = operated_node(&dir, &operator);
+        let mut controller = connect(&mut node, operator.clone());
+        let cas = |expected, val: &str| {
+            Message::compare_and_set(operator.id(), "key".into(), expected, val.into()).signed(&operator)
+        };
+
+        // no value yet, as expected
+        let (outcome, heads) = compare_and_set(&mut controller, &mut node, cas(None, "a"));
+        assert_eq!(outcome, SwapOutcome::Written);
+        assert_eq!(heads.len(), 1);
+        assert_eq!(node.store().heads("key"), heads);
+        let first = heads[0].hash();
+
+        // the key has a value now
+        let (outcome, heads) = compare_and_set(&mut controller, &mut node, cas(None, "b"));
+        assert_eq!(outcome, SwapOutcome::Changed);
+        assert_eq!(heads.iter().map(Entry::hash).collect::<Vec<_>>(), vec![first]);
+
+        let (outcome, heads) = compare_and_set(&mut controller, &mut node, cas(Some(first), "c"));
+        assert_eq!(outcome, SwapOutcome::Written);
+        assert_eq!(node.store().heads("key"), heads);
+        assert_ne!(heads[0].hash(), first);
+
+        // the value read is no longer the head
+        let (outcome, _heads) = compare_and_set(&mut controller, &mut node, cas(Some(first), "d"));
+        assert_eq!(outcome, SwapOutcome::Changed);
+        assert_eq!(node.store().entries().count(), 2);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn compare_and_set_is_refused_to_others_and_for_keys_the_node_may_not_write() {
+        let dir = data_dir("compare_and_set_refused");
+        let operator = SecretId::generate();
+        let mut node = operated_node(&dir, &operator);
+        let mut controller = connect(&mut node, operator.clone());
+        let stranger = SecretId::generate();
+        let mut other = connect(&mut node, stranger.clone());
+
+        let msg = Message::compare_and_set(stranger.id(), "key".into(), None, "a".into()).signed(&stranger);
+        let (outcome, heads) = compare_and_set(&mut other, &mut node, msg);
+        assert_eq!(outcome, SwapOutcome::Refused);
+        assert!(heads.is_empty());
+
+        // another author's namespace, without a grant
+        let key = format!("{}/key", stranger.id());
+        let msg = Message::compare_and_set(operator.id(), key, None, "a".into()).signed(&operator);
+        let (outcome, _heads) = compare_and_set(&mut controller, &mut node, msg);
+        assert_eq!(outcome, SwapOutcome::Refused);
+        assert_eq!(node.store().entries().count(), 0);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn gossiped_batches_are_stored_only_when_complete() {
+        let dir = data_dir("batches");
+        let operator = SecretId::generate();
+        let mut node = operated_node(&dir, &operator);
+        let addr = node.local_addr().unwrap();
+        let mut peer = connect(&mut node, operator.clone());
+
+        // only entries from trusted authors are stored
+        let author = SecretId::generate();
+        peer.send(addr, Message::trust(operator.id(), author.id(), 2600).signed(&operator));
+        node.step();
+
+        let seq = Clock::new().tick();
+        let batch: Vec<_> = ["a", "b"]
+            .into_iter()
+            .map(|key| {
+                let mut entry = Entry::new(&author, seq, key.into(), "val".into());
+                entry.batch = 2;
+                entry.sign(&author);
+                entry
+            })
+            .collect();
+
+        // missing an entry
+        peer.send(addr, Message::values(operator.id(), batch[..1].to_vec()));
+        node.step();
+        // with an entry that was tampered with
+        let mut forged = batch.clone();
+        forged[1].key = "c".into();
+        peer.send(addr, Message::values(operator.id(), forged));
+        node.step();
+        assert_eq!(node.store().entries().count(), 0);
+
+        peer.send(addr, Message::values(operator.id(), batch.clone()));
+        node.step();
+        assert!(batch.iter().all(|entry| node.store().contains(entry)));
+        assert_eq!(node.store().entries().count(), 2);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn expired_entries_are_not_passed_on() {
+        let dir = data_dir("expired");
+        let operator = SecretId::generate();
+        let mut node = operated_node(&dir, &operator);
+        let addr = node.local_addr().unwrap();
+        let mut peer = connect(&mut node, operator.clone());
+        let neighbor_secret = SecretId::generate();
+        let mut neighbor = connect(&mut node, neighbor_secret.clone());
+        let author = SecretId::generate();
+        peer.send(addr, Message::trust(operator.id(), author.id(), 2600).signed(&operator));
+        node.step();
+
+        let mut clock = Clock::new();
+        let mut expiring = |key: &str, expires: u64| {
+            let mut entry = Entry::new(&author, clock.tick(), key.into(), "val".into());
+            entry.expires = Some(expires);
+            entry.sign(&author);
+            entry
+        };
+        let expired = expiring("expired", now() - 1);
+        let brief = expiring("brief", now() + 200);
+
+        // the expired entry is dropped, so the first gossip the neighbor hears is the brief one
+        peer.send(addr, Message::values(operator.id(), vec![expired]));
+        node.step();
+        peer.send(addr, Message::values(operator.id(), vec![brief.clone()]));
+        node.step();
+        let msg = recv(&mut neighbor, &mut node).unwrap();
+        assert_eq!(msg.msg_type(), &MessageType::Values(vec![brief.clone()]));
+        assert_eq!(node.store().entries().collect::<Vec<_>>(), vec![&brief]);
+
+        // and once it has expired it is not read back either
+        std::thread::sleep(Duration::from_millis(300));
+        neighbor.send(addr, Message::get(neighbor_secret.id(), "brief".into(), 10));
+        node.step();
+        let MessageType::Got { state, entries, .. } = recv(&mut neighbor, &mut node).unwrap().take_msg_type() else {
+            panic!("the node should reply with the key's values")
+        };
+        assert_eq!(state, None);
+        assert!(entries.is_empty());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,65 @@
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, fs, net::SocketAddr, path::PathBuf};
+
+    use ddb_node::{MAX_PEERS, PeerCache};
+
+    /// An empty directory of its own for each test
+    fn data_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ddb_peer_cache_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn samples_are_random_known_peers() {
+        let dir = data_dir("sample");
+        let addrs: Vec<SocketAddr> = (0..4).map(|port| SocketAddr::from(([127, 0, 0, 1], 9000 + port))).collect();
+        let mut peers = PeerCache::open(&dir).unwrap();
+        assert!(peers.sample(2).is_empty());
+        for addr in &addrs {
+            peers.seen(*addr);
+        }
+        // a reliable peer is still not the only one picked
+        for _ in 0..10 {
+            peers.seen(addrs[0]);
+        }
+
+        let mut picked = HashSet::new();
+        for _ in 0..200 {
+            let sample = peers.sample(2);
+            assert_eq!(sample.len(), 2);
+            assert_ne!(sample[0], sample[1]);
+            assert!(sample.iter().all(|addr| addrs.contains(addr)));
+            picked.extend(sample);
+        }
+        assert_eq!(picked.len(), addrs.len());
+
+        // and the cache is there after a restart, once flushed
+        assert!(PeerCache::open(&dir).unwrap().sample(10).is_empty());
+        peers.flush();
+        let reopened = PeerCache::open(&dir).unwrap();
+        assert_eq!(reopened.sample(10).into_iter().collect::<HashSet<_>>(), picked);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn the_least_reliable_peers_are_forgotten_beyond_the_cap() {
+        let dir = data_dir("cap");
+        let mut peers = PeerCache::open(&dir).unwrap();
+        let reliable = SocketAddr::from(([127, 0, 0, 1], 1));
+        peers.seen(reliable);
+        peers.seen(reliable);
+        for port in 0..MAX_PEERS as u16 {
+            peers.seen(SocketAddr::from(([127, 0, 0, 2], port)));
+        }
+
+        let sample = peers.sample(MAX_PEERS + 1);
+        assert_eq!(sample.len(), MAX_PEERS);
+        assert!(sample.contains(&reliable));
+        // the peer just seen is kept too
+        assert!(sample.contains(&SocketAddr::from(([127, 0, 0, 2], MAX_PEERS as u16 - 1))));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
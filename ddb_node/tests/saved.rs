@@ -0,0 +1,71 @@
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use ddb_lib::SecretId;
+    use ddb_node::{Identification, saved};
+
+    #[derive(Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct Saved {
+        names: Vec<String>,
+    }
+
+    /// An empty directory of its own for each test
+    fn data_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ddb_saved_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn saved_files_load_back_with_their_version() {
+        let dir = data_dir("round_trip");
+        let path = dir.join("saved.json");
+        let data = Saved {
+            names: vec!["a".into(), "b".into()],
+        };
+
+        // nothing saved yet
+        assert_eq!(saved::load::<Saved>(&path, 1).unwrap(), Saved::default());
+
+        saved::save(&path, 1, &data).unwrap();
+        assert_eq!(saved::load::<Saved>(&path, 1).unwrap(), data);
+        let json: serde_json::Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(json["version"], 1);
+        // the temporary file was renamed into place
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn newer_versions_are_refused() {
+        let dir = data_dir("newer");
+        let path = dir.join("saved.json");
+        saved::save(&path, 2, &Saved::default()).unwrap();
+        assert!(saved::load::<Saved>(&path, 1).is_err());
+        assert!(saved::load::<Saved>(&path, 2).is_ok());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn reported_trust_is_saved_when_flushed() {
+        let dir = data_dir("trust");
+        let us = SecretId::generate().id();
+        let (peer, other) = (SecretId::generate().id(), SecretId::generate().id());
+
+        let mut identification = Identification::open(us, &dir).unwrap();
+        identification.change_trust(peer, 0.25);
+        identification.adjust_offset(peer, other, 1.0);
+        let trust = identification.get_trust(&other);
+        assert!(trust > 0.5);
+
+        // changes to our own trust are saved straight away, reports wait for a flush
+        let reopened = Identification::open(us, &dir).unwrap();
+        assert_eq!(reopened.get_trust(&peer), 0.75);
+        assert_eq!(reopened.get_trust(&other), 0.5);
+        identification.flush();
+        assert_eq!(Identification::open(us, &dir).unwrap().get_trust(&other), trust);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}